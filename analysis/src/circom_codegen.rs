@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use super::instructions::*;
 
@@ -28,6 +28,15 @@ impl CircomModule {
             main,
         }
     }
+
+    pub fn templates(&self) -> &[Template] {
+        &self.templates
+    }
+
+    /// Name of the template instantiated as the main component.
+    pub fn main(&self) -> &str {
+        &self.main
+    }
 }
 
 impl CircomCodeGenerator for CircomModule {
@@ -35,7 +44,7 @@ impl CircomCodeGenerator for CircomModule {
         let mut circom_code = self.pragma();
         circom_code.push_str(&self.includes());
         circom_code.push_str("\n\n");
-        circom_code.push_str(&self.templates());
+        circom_code.push_str(&self.templates_code());
         circom_code.push_str(&self.main_component());
         circom_code
     }
@@ -52,7 +61,7 @@ impl CircomModule {
         }
         circom_code
     }
-    fn templates(&self) -> String {
+    fn templates_code(&self) -> String {
         let mut circom_code = String::new();
         for t in &self.templates {
             circom_code.push_str(&t.to_circom());
@@ -97,10 +106,11 @@ impl ComponentIncludes {
         }
     }
     pub fn component_includes(&self) -> Vec<String> {
-        self.used
-            .iter()
-            .map(|u| self.known.get(u).map(|u| format!("include \"{u}\";")))
-            .flatten()
+        // Several components may live in the same file, include each file once.
+        let files: BTreeSet<&String> = self.used.iter().filter_map(|u| self.known.get(u)).collect();
+        files
+            .into_iter()
+            .map(|f| format!("include \"{f}\";"))
             .collect()
     }
 }
@@ -113,13 +123,20 @@ impl CircomCodeGenerator for CircomInstr {
             CircomInstr::ComponentInstatiation(component_instatiation) => {
                 component_instatiation.to_circom()
             }
+            CircomInstr::ConstraintEquality(constraint) => constraint.to_circom(),
         }
     }
 }
 
 impl CircomCodeGenerator for ComponentInstatiation {
     fn to_circom(&self) -> String {
-        format!("component {} = {}();", self.name, self.component)
+        let args = self
+            .args
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("component {} = {}({});", self.name, self.component, args)
     }
 }
 
@@ -127,6 +144,7 @@ impl CircomCodeGenerator for SignalDeclaration {
     fn to_circom(&self) -> String {
         match self {
             SignalDeclaration::Input(name) => format!("signal input {};", name),
+            SignalDeclaration::InputArray(name, len) => format!("signal input {}[{}];", name, len),
             SignalDeclaration::Private(name) => format!("signal {};", name),
             SignalDeclaration::Output(name) => format!("signal output {};", name),
         }
@@ -139,6 +157,12 @@ impl CircomCodeGenerator for ConstraintGenerationAssigment {
     }
 }
 
+impl CircomCodeGenerator for ConstraintEquality {
+    fn to_circom(&self) -> String {
+        format!("{} === {};", self.left.to_circom(), self.right.to_circom())
+    }
+}
+
 impl CircomCodeGenerator for Reference {
     fn to_circom(&self) -> String {
         match self {
@@ -155,6 +179,13 @@ impl CircomCodeGenerator for Expression {
             Expression::BinaryOperation(bin_op) => bin_op.to_circom(),
             Expression::Conditional(cond) => cond.to_circom(),
             Expression::BinaryOr(binary_or) => binary_or.to_circom(),
+            Expression::BinaryXor(binary_xor) => binary_xor.to_circom(),
+            Expression::Sum(operands) if operands.is_empty() => "0".to_string(),
+            Expression::Sum(operands) => operands
+                .iter()
+                .map(|o| o.to_circom())
+                .collect::<Vec<_>>()
+                .join(" + "),
         }
     }
 }
@@ -197,6 +228,18 @@ impl CircomCodeGenerator for ConditionalValue {
     }
 }
 
+impl CircomCodeGenerator for BinaryXor {
+    fn to_circom(&self) -> String {
+        format!(
+            "({} + {}) - (2 * {} * {})",
+            self.a.to_circom(),
+            self.b.to_circom(),
+            self.a.to_circom(),
+            self.b.to_circom()
+        )
+    }
+}
+
 impl CircomCodeGenerator for BinaryOr {
     fn to_circom(&self) -> String {
         format!(
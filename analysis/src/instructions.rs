@@ -1,4 +1,8 @@
 use llvm_ir::{Constant, Name, Operand};
+use num_bigint::BigUint;
+use std::fmt;
+
+use crate::ir_circom::diagnostics::{TranslationError, TranslationResult, unsupported};
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Template {
@@ -11,6 +15,38 @@ pub enum CircomInstr {
     SignalDeclaration(SignalDeclaration),
     ConstraintGenerationAssigment(ConstraintGenerationAssigment),
     ComponentInstatiation(ComponentInstatiation),
+    ConstraintEquality(ConstraintEquality),
+}
+
+impl CircomInstr {
+    /// The same instruction with every signal and component name prefixed by `prefix`.
+    pub fn prefixed(&self, prefix: &str) -> Self {
+        match self {
+            Self::SignalDeclaration(s) => Self::SignalDeclaration(match s {
+                SignalDeclaration::Input(s) => SignalDeclaration::Input(format!("{prefix}{s}")),
+                SignalDeclaration::InputArray(s, len) => {
+                    SignalDeclaration::InputArray(format!("{prefix}{s}"), *len)
+                }
+                SignalDeclaration::Private(s) => SignalDeclaration::Private(format!("{prefix}{s}")),
+                SignalDeclaration::Output(s) => SignalDeclaration::Output(format!("{prefix}{s}")),
+            }),
+            Self::ConstraintGenerationAssigment(c) => {
+                Self::ConstraintGenerationAssigment(ConstraintGenerationAssigment {
+                    left: c.left.prefixed(prefix),
+                    right: c.right.prefixed(prefix),
+                })
+            }
+            Self::ComponentInstatiation(c) => Self::ComponentInstatiation(ComponentInstatiation {
+                name: format!("{prefix}{}", c.name),
+                component: c.component.clone(),
+                args: c.args.clone(),
+            }),
+            Self::ConstraintEquality(c) => Self::ConstraintEquality(ConstraintEquality {
+                left: c.left.prefixed(prefix),
+                right: c.right.prefixed(prefix),
+            }),
+        }
+    }
 }
 
 impl From<SignalDeclaration> for CircomInstr {
@@ -31,10 +67,17 @@ impl From<ComponentInstatiation> for CircomInstr {
     }
 }
 
+impl From<ConstraintEquality> for CircomInstr {
+    fn from(value: ConstraintEquality) -> Self {
+        Self::ConstraintEquality(value)
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ComponentInstatiation {
     pub name: String,
     pub component: String,
+    pub args: Vec<u32>,
 }
 
 impl ComponentInstatiation {
@@ -49,6 +92,8 @@ impl ComponentInstatiation {
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SignalDeclaration {
     Input(String),
+    /// `signal input name[len]`
+    InputArray(String, usize),
     Private(String),
     Output(String),
 }
@@ -56,7 +101,9 @@ pub enum SignalDeclaration {
 impl SignalDeclaration {
     pub fn reference(&self) -> Reference {
         match self {
-            Self::Input(s) | Self::Output(s) | Self::Private(s) => Reference::SignalRef(s.clone()),
+            Self::Input(s) | Self::Output(s) | Self::Private(s) | Self::InputArray(s, _) => {
+                Reference::SignalRef(s.clone())
+            }
         }
     }
 }
@@ -67,6 +114,13 @@ pub struct ConstraintGenerationAssigment {
     pub right: Expression,
 }
 
+/// A constraint that does not assign any signal (`left === right`).
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConstraintEquality {
+    pub left: Expression,
+    pub right: Expression,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Reference {
     SignalRef(String),
@@ -74,10 +128,19 @@ pub enum Reference {
 }
 
 impl Reference {
-    pub fn intermediate_optional(&self) -> String {
+    pub fn intermediate_optional(&self) -> TranslationResult<String> {
         match self {
-            Self::SignalRef(s) => format!("{s}O"),
-            _ => unimplemented!(),
+            Self::SignalRef(s) => Ok(format!("{s}O")),
+            other => unsupported!("Conditional assignment of {other:?}"),
+        }
+    }
+    pub fn prefixed(&self, prefix: &str) -> Self {
+        match self {
+            Self::SignalRef(s) => Self::SignalRef(format!("{prefix}{s}")),
+            Self::ComponentField { component, field } => Self::ComponentField {
+                component: format!("{prefix}{component}"),
+                field: field.clone(),
+            },
         }
     }
 }
@@ -85,20 +148,71 @@ impl Reference {
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CircomOperand {
     Reference(Reference),
-    Constant(i64),
+    Constant(IntConstant),
+}
+
+impl CircomOperand {
+    /// The `bits`-bit integer constant `value`.
+    pub fn constant(value: impl Into<BigUint>, bits: u32) -> Self {
+        Self::Constant(IntConstant::new(value, bits))
+    }
+    /// Whether the operand is the constant `value`.
+    pub fn is_constant(&self, value: u64) -> bool {
+        matches!(self, Self::Constant(c) if c.value == BigUint::from(value))
+    }
+    pub fn prefixed(&self, prefix: &str) -> Self {
+        match self {
+            Self::Reference(r) => Self::Reference(r.prefixed(prefix)),
+            Self::Constant(c) => Self::Constant(c.clone()),
+        }
+    }
+}
+
+/// A constant of the LLVM integer type `i{bits}`. Signals of that type carry the unsigned
+/// value of the integer, so negative constants are kept in two's complement and the value
+/// always fits in `bits` bits.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IntConstant {
+    pub value: BigUint,
+    pub bits: u32,
 }
 
-impl From<&llvm_ir::Operand> for CircomOperand {
-    fn from(value: &llvm_ir::Operand) -> Self {
+impl IntConstant {
+    /// The constant `value` truncated to `bits` bits.
+    pub fn new(value: impl Into<BigUint>, bits: u32) -> Self {
+        let mask = (BigUint::from(1u32) << bits) - 1u32;
+        Self {
+            value: value.into() & mask,
+            bits,
+        }
+    }
+    pub fn to_u64(&self) -> Option<u64> {
+        u64::try_from(&self.value).ok()
+    }
+}
+
+impl fmt::Display for IntConstant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl TryFrom<&llvm_ir::Operand> for CircomOperand {
+    type Error = TranslationError;
+
+    fn try_from(value: &llvm_ir::Operand) -> TranslationResult<Self> {
         match value {
-            llvm_ir::Operand::LocalOperand { name, .. } => {
-                CircomOperand::Reference(Reference::SignalRef(name.to_simple_string()))
-            }
+            llvm_ir::Operand::LocalOperand { name, .. } => Ok(CircomOperand::Reference(
+                Reference::SignalRef(name.to_simple_string()),
+            )),
             llvm_ir::Operand::ConstantOperand(c) => match c.as_ref() {
-                Constant::Int { value, .. } => CircomOperand::Constant(*value as i64),
-                _ => unimplemented!(),
+                // Only the low 64 bits of wider constants are known here, see `IrModule`.
+                Constant::Int { bits, value } if *bits <= 64 => {
+                    Ok(CircomOperand::constant(*value, *bits))
+                }
+                _ => unsupported!("Constant operand {value}"),
             },
-            _ => unimplemented!(),
+            other => unsupported!("Operand {other}"),
         }
     }
 }
@@ -109,6 +223,35 @@ pub enum Expression {
     BinaryOperation(BinaryOperation),
     Conditional(ConditionalValue),
     BinaryOr(BinaryOr),
+    BinaryXor(BinaryXor),
+    Sum(Vec<CircomOperand>),
+}
+
+impl Expression {
+    pub fn prefixed(&self, prefix: &str) -> Self {
+        match self {
+            Self::Operand(o) => Self::Operand(o.prefixed(prefix)),
+            Self::BinaryOperation(b) => Self::BinaryOperation(BinaryOperation {
+                left: b.left.prefixed(prefix),
+                op: b.op.clone(),
+                right: b.right.prefixed(prefix),
+            }),
+            Self::Conditional(c) => Self::Conditional(ConditionalValue {
+                cond: c.cond.prefixed(prefix),
+                v_if_true: c.v_if_true.prefixed(prefix),
+                v_if_false: c.v_if_false.prefixed(prefix),
+            }),
+            Self::BinaryOr(o) => Self::BinaryOr(BinaryOr {
+                a: o.a.prefixed(prefix),
+                b: o.b.prefixed(prefix),
+            }),
+            Self::BinaryXor(x) => Self::BinaryXor(BinaryXor {
+                a: x.a.prefixed(prefix),
+                b: x.b.prefixed(prefix),
+            }),
+            Self::Sum(operands) => Self::Sum(operands.iter().map(|o| o.prefixed(prefix)).collect()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -131,6 +274,12 @@ pub struct BinaryOr {
     pub b: CircomOperand,
 }
 
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BinaryXor {
+    pub a: CircomOperand,
+    pub b: CircomOperand,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum BinaryOperationType {
     Mul,
@@ -156,12 +305,29 @@ impl IRNameToSimpleString for Name {
     }
 }
 
-impl IRNameToSimpleString for Operand {
-    fn to_simple_string(&self) -> String {
+pub trait IRIntegerBits {
+    /// Bit width of the LLVM integer type, `None` for non-integer types.
+    fn integer_bits(&self) -> Option<u32>;
+}
+
+impl IRIntegerBits for llvm_ir::Type {
+    fn integer_bits(&self) -> Option<u32> {
         match self {
-            Operand::LocalOperand { name, .. } => name.to_simple_string(),
-            Operand::ConstantOperand(..) => unimplemented!(),
-            _ => unimplemented!(),
+            llvm_ir::Type::IntegerType { bits } => Some(*bits),
+            _ => None,
+        }
+    }
+}
+
+impl IRIntegerBits for Operand {
+    fn integer_bits(&self) -> Option<u32> {
+        match self {
+            Operand::LocalOperand { ty, .. } => ty.integer_bits(),
+            Operand::ConstantOperand(c) => match c.as_ref() {
+                Constant::Int { bits, .. } => Some(*bits),
+                _ => None,
+            },
+            _ => None,
         }
     }
 }
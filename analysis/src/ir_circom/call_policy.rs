@@ -0,0 +1,264 @@
+use std::fmt::Display;
+
+use llvm_ir::instruction::Call;
+
+/// What to do with a call, chosen by the first rule of the [`CallPolicy`] matching the callee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallAction {
+    /// Drop the call, recording it in the [`TranslationReport`].
+    Ignore,
+    /// Splice the constraints of the callee into the caller template.
+    Inline,
+    /// Instantiate the template the callee is translated to (intrinsics are lowered instead).
+    Template,
+    /// Instantiate a circom component, wiring the arguments to `in` and the result to `out`.
+    Component {
+        name: String,
+        args: Vec<u32>,
+        include: Option<String>,
+    },
+    /// Refuse to translate the call.
+    Reject,
+}
+
+/// Maps callee names to the way their calls are translated. Patterns are matched against
+/// the whole name, `*` standing for any sequence of characters.
+#[derive(Debug, Clone)]
+pub struct CallPolicy {
+    rules: Vec<(String, CallAction)>,
+}
+
+impl Default for CallPolicy {
+    /// Intrinsics only carrying optimization hints are ignored, anything else is translated.
+    /// Debug info intrinsics are always dropped, without being reported.
+    fn default() -> Self {
+        Self::empty()
+            .with_rule("llvm.lifetime.*", CallAction::Ignore)
+            .with_rule("llvm.assume", CallAction::Ignore)
+            .with_rule("llvm.experimental.noalias.scope.decl", CallAction::Ignore)
+    }
+}
+
+impl CallPolicy {
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    /// Adds a rule taking precedence over the ones added before.
+    pub fn with_rule(mut self, pattern: impl Into<String>, action: CallAction) -> Self {
+        self.rules.insert(0, (pattern.into(), action));
+        self
+    }
+
+    /// Adds the rules of a policy file on top of the current ones. Every non-empty line that
+    /// is not a `#` comment is a `pattern = action` rule, where the action is one of `ignore`,
+    /// `inline`, `template`, `reject` or `component Name(args) [from include.circom]`.
+    /// The rule is split at its first `=`, so patterns cannot contain one. Later lines take
+    /// precedence.
+    pub fn with_config(mut self, config: &str) -> Result<Self, String> {
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: &dyn Display| format!("line {}: {e}: {line}", i + 1);
+            let Some((pattern, action)) = line.split_once('=') else {
+                return Err(error(&"expected `pattern = action`"));
+            };
+            let action = parse_action(action.trim()).map_err(|e| error(&e))?;
+            self = self.with_rule(pattern.trim(), action);
+        }
+        Ok(self)
+    }
+
+    pub fn action(&self, callee: &str) -> CallAction {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_matches(pattern, callee))
+            .map(|(_, action)| action.clone())
+            .unwrap_or(CallAction::Template)
+    }
+
+    /// Circom components the policy maps calls to, with the files declaring them.
+    pub fn components(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.rules.iter().filter_map(|(_, action)| match action {
+            CallAction::Component {
+                name,
+                include: Some(include),
+                ..
+            } => Some((name.as_str(), include.as_str())),
+            _ => None,
+        })
+    }
+}
+
+fn parse_action(action: &str) -> Result<CallAction, String> {
+    let action = match action {
+        "ignore" => CallAction::Ignore,
+        "inline" => CallAction::Inline,
+        "template" => CallAction::Template,
+        "reject" => CallAction::Reject,
+        other => {
+            let Some(component) = other.strip_prefix("component ") else {
+                return Err(format!("unknown action `{other}`"));
+            };
+            let (component, include) = match component.split_once(" from ") {
+                Some((component, include)) => (component, Some(include.trim().to_string())),
+                None => (component, None),
+            };
+            let (name, args) = match component.trim().split_once('(') {
+                Some((name, args)) => match args.strip_suffix(')') {
+                    Some(args) => (name, args),
+                    None => return Err(format!("unclosed arguments of `{component}`")),
+                },
+                None => (component.trim(), ""),
+            };
+            let args = args
+                .split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(|a| {
+                    a.parse()
+                        .map_err(|e| format!("invalid argument `{a}`: {e}"))
+                })
+                .collect::<Result<_, _>>()?;
+            CallAction::Component {
+                name: name.trim().to_string(),
+                args,
+                include,
+            }
+        }
+    };
+    Ok(action)
+}
+
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| glob_matches(rest, &name[i..]))
+        }
+    }
+}
+
+/// Calls left out of the circuit by the translation.
+#[derive(Debug, Clone, Default)]
+pub struct TranslationReport {
+    /// The function containing the call and the call itself.
+    pub ignored_calls: Vec<(String, String)>,
+}
+
+impl TranslationReport {
+    pub fn ignore_call(&mut self, function: String, call: &Call) {
+        let mut text = call.to_string();
+        if call.dest.is_some() {
+            text.push_str(" (result left unconstrained)");
+        }
+        let entry = (function, text);
+        // Unrolled loops repeat their calls.
+        if !self.ignored_calls.contains(&entry) {
+            self.ignored_calls.push(entry);
+        }
+    }
+    pub fn extend(&mut self, other: TranslationReport) {
+        for entry in other.ignored_calls {
+            if !self.ignored_calls.contains(&entry) {
+                self.ignored_calls.push(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_whole_names() {
+        assert!(glob_matches("llvm.assume", "llvm.assume"));
+        assert!(!glob_matches("llvm.assume", "llvm.assume.x"));
+        assert!(!glob_matches("assume", "llvm.assume"));
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_matches("llvm.lifetime.*", "llvm.lifetime.start.p0"));
+        assert!(glob_matches("llvm.lifetime.*", "llvm.lifetime."));
+        assert!(!glob_matches("llvm.lifetime.*", "llvm.memcpy.p0.p0.i64"));
+        assert!(glob_matches("*hash*", "_ZN4core4hash3sip6hasher"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("a*b*c", "aXcYb"));
+        assert!(glob_matches("é*ü", "éàü"));
+    }
+
+    #[test]
+    fn parse_action_keywords() {
+        assert_eq!(parse_action("ignore"), Ok(CallAction::Ignore));
+        assert_eq!(parse_action("inline"), Ok(CallAction::Inline));
+        assert_eq!(parse_action("template"), Ok(CallAction::Template));
+        assert_eq!(parse_action("reject"), Ok(CallAction::Reject));
+        assert!(parse_action("drop").is_err());
+    }
+
+    #[test]
+    fn parse_action_components() {
+        assert_eq!(
+            parse_action("component Poseidon(2) from circomlib/poseidon.circom"),
+            Ok(CallAction::Component {
+                name: "Poseidon".to_string(),
+                args: vec![2],
+                include: Some("circomlib/poseidon.circom".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_action("component Num2Bits( 8 , 3 )"),
+            Ok(CallAction::Component {
+                name: "Num2Bits".to_string(),
+                args: vec![8, 3],
+                include: None,
+            })
+        );
+        assert_eq!(
+            parse_action("component IsZero"),
+            Ok(CallAction::Component {
+                name: "IsZero".to_string(),
+                args: vec![],
+                include: None,
+            })
+        );
+        assert!(parse_action("component Foo(1").is_err());
+        assert!(parse_action("component Foo(x)").is_err());
+    }
+
+    #[test]
+    fn config_splits_at_the_first_equal_sign() {
+        let policy = CallPolicy::empty()
+            .with_config("# comment\n\nfoo = component Foo(1) from a=b.circom\n")
+            .unwrap();
+        assert_eq!(
+            policy.action("foo"),
+            CallAction::Component {
+                name: "Foo".to_string(),
+                args: vec![1],
+                include: Some("a=b.circom".to_string()),
+            }
+        );
+        assert!(CallPolicy::empty().with_config("foo inline").is_err());
+    }
+
+    #[test]
+    fn later_rules_take_precedence() {
+        let policy = CallPolicy::default()
+            .with_config("llvm.* = reject\nllvm.assume = inline")
+            .unwrap();
+        assert_eq!(policy.action("llvm.assume"), CallAction::Inline);
+        assert_eq!(policy.action("llvm.lifetime.start.p0"), CallAction::Reject);
+        assert_eq!(policy.action("foo"), CallAction::Template);
+    }
+}
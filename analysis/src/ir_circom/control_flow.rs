@@ -2,130 +2,194 @@ use std::collections::{HashMap, HashSet, VecDeque};
 
 use llvm_ir::{BasicBlock, Name, Terminator};
 
-use crate::{circom_codegen::CircomCodeGenerator, instructions::CircomOperand};
+use crate::{
+    instructions::{CircomOperand, IRNameToSimpleString, Reference},
+    ir_circom::{
+        diagnostics::{TranslationResult, unsupported},
+        loops::BlockInstance,
+    },
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct BranchNode(pub CircomOperand, pub bool);
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
-pub struct SimpleBranch(pub Vec<BranchNode>);
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Branch {
-    Simple(SimpleBranch),
-    Or(Vec<Branch>),
+#[derive(Debug, Clone)]
+pub struct CFGResult {
+    pub sorted: Vec<BlockInstance>,
+    /// Blocks executed exactly when their immediate dominator is, mapped to that dominator.
+    pub equivalent: HashMap<Name, Name>,
+    pub parents: HashMap<Name, Vec<ParentInfo>>,
 }
 
-impl Branch {
-    pub fn from_many(other: Vec<Branch>) -> Branch {
-        if other.is_empty() {
-            Branch::Simple(SimpleBranch::default())
-        } else if other.len() == 1 {
-            other[0].clone()
-        } else {
-            Branch::Or(other)
-        }
-    }
-    pub fn add_child(&self, child: &ParentInfo) -> Self {
-        match self {
-            Self::Simple(s) => Self::Simple(s.add_child(child)),
-            Self::Or(or) => Self::Or(or.iter().map(|s| s.add_child(child)).collect()),
-        }
-    }
+pub fn compute_cfg(instances: &[BlockInstance]) -> TranslationResult<CFGResult> {
+    let children = children(instances.iter().map(|i| &i.block))?;
+    let parents = parents(&children);
+    let sorted = topological_sort(&children, &parents)?;
+    let equivalent = match instances.first() {
+        Some(entry) => equivalent_blocks(&entry.block.name, instances)?,
+        None => HashMap::new(),
+    };
+    let blocks: HashMap<_, _> = instances.iter().map(|i| (&i.block.name, i)).collect();
+    let sorted = sorted
+        .into_iter()
+        .map(|n| match blocks.get(&n) {
+            Some(instance) => Ok((*instance).clone()),
+            None => unsupported!("Branch to the unknown block {n}"),
+        })
+        .collect::<TranslationResult<_>>()?;
+    Ok(CFGResult {
+        sorted,
+        equivalent,
+        parents,
+    })
 }
 
-impl SimpleBranch {
-    pub fn add_child(&self, child: &ParentInfo) -> Self {
-        match child {
-            pi @ (ParentInfo::TrueBranch(_, circom_operand)
-            | ParentInfo::FalseBranch(_, circom_operand)) => {
-                let mut conds = self.0.clone();
-                conds.push(BranchNode(circom_operand.clone(), pi.as_bool().unwrap()));
-                Self(conds)
-            }
-            ParentInfo::Merge(_) => self.clone(),
+/// Blocks post-dominating their immediate dominator. In the acyclic CFG of the unrolled
+/// function, control reaching the dominator always goes on to such a block, so both share
+/// the same execution predicate.
+fn equivalent_blocks(
+    entry: &Name,
+    instances: &[BlockInstance],
+) -> TranslationResult<HashMap<Name, Name>> {
+    let successors: HashMap<Name, Vec<Name>> = instances
+        .iter()
+        .map(|i| Ok((i.block.name.clone(), successors(&i.block.term)?)))
+        .collect::<TranslationResult<_>>()?;
+    // Post-dominators are the dominators of the reversed CFG, entered from a virtual exit
+    // block that every returning (or `unreachable`) block flows into.
+    let exit = Name::from("\0exit");
+    let mut predecessors: HashMap<Name, Vec<Name>> = successors
+        .keys()
+        .map(|b| (b.clone(), vec![]))
+        .chain([(exit.clone(), vec![])])
+        .collect();
+    for (block, next) in &successors {
+        for next in next {
+            predecessors
+                .entry(next.clone())
+                .or_default()
+                .push(block.clone());
         }
-    }
-    pub fn parent(&self) -> Self {
-        if self.0.is_empty() {
-            return self.clone();
+        if next.is_empty() {
+            predecessors
+                .entry(exit.clone())
+                .or_default()
+                .push(block.clone());
         }
-        return Self(self.0.iter().cloned().take(self.0.len() - 1).collect());
-    }
-    pub fn leaf_condition(&self) -> Option<BranchNode> {
-        self.0.last().cloned()
     }
-    pub fn condition_operand_name(&self) -> String {
-        self.0
+    let post_dominators = dominators(&exit, &predecessors);
+    let dominators = dominators(entry, &successors);
+
+    let mut equivalent = HashMap::new();
+    for (block, dominated_by) in &dominators {
+        let immediate = dominated_by
             .iter()
-            .map(|BranchNode(operand, bool)| {
-                format!("{}{}", operand.to_circom(), if *bool { "" } else { "F" })
-            })
-            .collect()
+            .filter(|d| *d != block)
+            .max_by_key(|d| dominators[*d].len());
+        if let Some(immediate) = immediate
+            && post_dominators
+                .get(immediate)
+                .is_some_and(|p| p.contains(block))
+        {
+            equivalent.insert(block.clone(), immediate.clone());
+        }
     }
+    Ok(equivalent)
 }
 
-impl From<Vec<BranchNode>> for SimpleBranch {
-    fn from(value: Vec<BranchNode>) -> Self {
-        Self(value)
-    }
+#[derive(Debug, Clone)]
+enum ChildrenInfo {
+    None,
+    One(Name),
+    Condition(CircomOperand, Name, Name),
+    /// Every destination of a `switch` with the condition of taking it, the default included.
+    Switch(Vec<(CircomOperand, Name)>),
 }
 
-impl From<Vec<BranchNode>> for Branch {
-    fn from(value: Vec<BranchNode>) -> Self {
-        Self::Simple(SimpleBranch::from(value))
+/// Signal holding whether a `switch` terminating `block` takes its `case`-th destination,
+/// `None` standing for the default one.
+pub fn switch_case_signal(block: &Name, case: Option<usize>) -> String {
+    let block = block.to_simple_string();
+    match case {
+        Some(i) => format!("{block}_SW{i}"),
+        None => format!("{block}_SWD"),
     }
 }
 
-impl From<SimpleBranch> for Branch {
-    fn from(value: SimpleBranch) -> Self {
-        Self::Simple(value)
-    }
+/// Blocks `terminator` may transfer control to.
+pub fn successors(terminator: &Terminator) -> TranslationResult<Vec<Name>> {
+    let successors = match terminator {
+        Terminator::Ret(_) | Terminator::Unreachable(_) => vec![],
+        Terminator::Br(br) => vec![br.dest.clone()],
+        Terminator::CondBr(cond_br) => {
+            vec![cond_br.true_dest.clone(), cond_br.false_dest.clone()]
+        }
+        Terminator::Switch(switch) => switch
+            .dests
+            .iter()
+            .map(|(_, dest)| dest.clone())
+            .chain([switch.default_dest.clone()])
+            .collect(),
+        t => unsupported!("Terminator {t}"),
+    };
+    Ok(successors)
 }
 
-impl From<&SimpleBranch> for Branch {
-    fn from(value: &SimpleBranch) -> Self {
-        Branch::Simple(value.clone())
+/// Maps every block reachable from `entry` to the set of blocks dominating it, itself included.
+pub fn dominators(
+    entry: &Name,
+    successors: &HashMap<Name, Vec<Name>>,
+) -> HashMap<Name, HashSet<Name>> {
+    let mut order = vec![];
+    let mut seen = HashSet::from([entry.clone()]);
+    let mut queue = VecDeque::from([entry.clone()]);
+    while let Some(block) = queue.pop_front() {
+        for next in &successors[&block] {
+            if seen.insert(next.clone()) {
+                queue.push_back(next.clone());
+            }
+        }
+        order.push(block);
     }
-}
-
-#[derive(Debug, Clone)]
-pub struct CFGResult {
-    pub sorted: Vec<BasicBlock>,
-    pub branch_conditions: HashMap<Name, Branch>,
-}
-
-pub fn compute_cfg(blocks: &[BasicBlock]) -> CFGResult {
-    let children = children(blocks);
-    let parents = parents(&children);
-    let sorted = topological_sort(&children, &parents);
-    let conditions = branch_conditions(&sorted, &parents);
-    let blocks: HashMap<_, _> = blocks.into_iter().map(|b| (&b.name, b)).collect();
-    CFGResult {
-        sorted: sorted
-            .into_iter()
-            .map(|n| blocks.get(&n).unwrap().to_owned().clone())
-            .collect(),
-        branch_conditions: conditions,
+    let mut predecessors: HashMap<&Name, Vec<&Name>> = HashMap::new();
+    for block in &order {
+        for next in &successors[block] {
+            predecessors.entry(next).or_default().push(block);
+        }
     }
-}
 
-#[derive(Debug, Clone)]
-enum ChildrenInfo {
-    None,
-    One(Name),
-    Condition(CircomOperand, Name, Name),
+    let mut dominators: HashMap<Name, HashSet<Name>> =
+        order.iter().map(|b| (b.clone(), seen.clone())).collect();
+    dominators.insert(entry.clone(), HashSet::from([entry.clone()]));
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in order.iter().skip(1) {
+            let mut dom = predecessors[block]
+                .iter()
+                .map(|p| dominators[*p].clone())
+                .reduce(|a, b| a.intersection(&b).cloned().collect())
+                .unwrap_or_default();
+            dom.insert(block.clone());
+            if dom != dominators[block] {
+                dominators.insert(block.clone(), dom);
+                changed = true;
+            }
+        }
+    }
+    dominators
 }
 
-fn children(blocks: &[BasicBlock]) -> HashMap<Name, ChildrenInfo> {
+fn children<'a>(
+    blocks: impl Iterator<Item = &'a BasicBlock>,
+) -> TranslationResult<HashMap<Name, ChildrenInfo>> {
     let mut children = HashMap::new();
     for b in blocks {
         match &b.term {
-            Terminator::Ret(_) => children.insert(b.name.clone(), ChildrenInfo::None),
+            Terminator::Ret(_) | Terminator::Unreachable(_) => {
+                children.insert(b.name.clone(), ChildrenInfo::None)
+            }
             Terminator::CondBr(cond_br) => children.insert(
                 b.name.clone(),
                 ChildrenInfo::Condition(
-                    (&cond_br.condition).into(),
+                    (&cond_br.condition).try_into()?,
                     cond_br.true_dest.clone(),
                     cond_br.false_dest.clone(),
                 ),
@@ -133,10 +197,23 @@ fn children(blocks: &[BasicBlock]) -> HashMap<Name, ChildrenInfo> {
             Terminator::Br(br) => {
                 children.insert(b.name.clone(), ChildrenInfo::One(br.dest.clone()))
             }
-            _ => unimplemented!(),
+            Terminator::Switch(switch) => {
+                let case = |i| {
+                    CircomOperand::Reference(Reference::SignalRef(switch_case_signal(&b.name, i)))
+                };
+                let mut dests: Vec<_> = switch
+                    .dests
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, dest))| (case(Some(i)), dest.clone()))
+                    .collect();
+                dests.push((case(None), switch.default_dest.clone()));
+                children.insert(b.name.clone(), ChildrenInfo::Switch(dests))
+            }
+            t => unsupported!("Terminator {t}"),
         };
     }
-    children
+    Ok(children)
 }
 
 #[derive(Debug, Clone)]
@@ -147,14 +224,7 @@ pub enum ParentInfo {
 }
 
 impl ParentInfo {
-    fn as_bool(&self) -> Option<bool> {
-        match self {
-            ParentInfo::TrueBranch(..) => Some(true),
-            ParentInfo::FalseBranch(..) => Some(false),
-            ParentInfo::Merge(_) => None,
-        }
-    }
-    fn name(&self) -> &Name {
+    pub fn name(&self) -> &Name {
         match self {
             ParentInfo::TrueBranch(n, _) | ParentInfo::FalseBranch(n, _) | ParentInfo::Merge(n) => {
                 n
@@ -182,6 +252,14 @@ fn parents(children: &HashMap<Name, ChildrenInfo>) -> HashMap<Name, Vec<ParentIn
                     .or_default()
                     .push(ParentInfo::FalseBranch(n.clone(), op.clone()));
             }
+            ChildrenInfo::Switch(dests) => {
+                for (op, name) in dests {
+                    parents
+                        .entry(name.clone())
+                        .or_default()
+                        .push(ParentInfo::TrueBranch(n.clone(), op.clone()));
+                }
+            }
         }
     }
     parents
@@ -190,7 +268,7 @@ fn parents(children: &HashMap<Name, ChildrenInfo>) -> HashMap<Name, Vec<ParentIn
 fn topological_sort(
     children: &HashMap<Name, ChildrenInfo>,
     parents: &HashMap<Name, Vec<ParentInfo>>,
-) -> Vec<Name> {
+) -> TranslationResult<Vec<Name>> {
     let mut in_degree: HashMap<Name, usize> = HashMap::new();
 
     for name in children.keys() {
@@ -209,7 +287,7 @@ fn topological_sort(
     }
 
     if queue.is_empty() && !children.is_empty() {
-        panic!("Cycle!");
+        unsupported!("Cycle in the control flow graph");
     }
 
     let mut sorted_blocks = Vec::new();
@@ -221,6 +299,7 @@ fn topological_sort(
                 ChildrenInfo::None => vec![],
                 ChildrenInfo::One(name) => vec![name.clone()],
                 ChildrenInfo::Condition(_, t_name, f_name) => vec![t_name.clone(), f_name.clone()],
+                ChildrenInfo::Switch(dests) => dests.iter().map(|(_, n)| n.clone()).collect(),
             };
 
             for child in next_nodes {
@@ -235,51 +314,7 @@ fn topological_sort(
     }
 
     if sorted_blocks.len() != children.len() {
-        panic!("Cycle!")
-    } else {
-        sorted_blocks
-    }
-}
-
-fn branch_conditions(
-    sorted_blocks: &[Name],
-    parents_info: &HashMap<Name, Vec<ParentInfo>>,
-) -> HashMap<Name, Branch> {
-    let mut conditions: HashMap<Name, Branch> = HashMap::new();
-    for block in sorted_blocks {
-        let d = vec![];
-        let parents: &[ParentInfo] = parents_info.get(block).unwrap_or(&d);
-        let branch = Branch::from_many(
-            parents
-                .into_iter()
-                .map(|p| conditions.get(p.name()).unwrap().add_child(p))
-                .collect(),
-        );
-        conditions.insert(block.clone(), branch);
-    }
-    conditions
-}
-
-#[allow(dead_code)]
-fn branch_gca(parents: &[&Vec<BranchNode>]) -> Vec<BranchNode> {
-    if parents.is_empty() {
-        return vec![].into();
-    }
-    let mut gca = parents[0].clone();
-    for cur in parents.iter().skip(1) {
-        gca = branch_gca_2(&gca, cur);
-    }
-    gca.into()
-}
-
-fn branch_gca_2(a: &[BranchNode], b: &[BranchNode]) -> Vec<BranchNode> {
-    let b: HashSet<_> = b.into_iter().collect();
-    let a: HashSet<_> = a.into_iter().collect();
-    let mut result = vec![];
-    for &x in a.iter() {
-        if b.contains(x) {
-            result.push(x.clone());
-        }
+        unsupported!("Cycle in the control flow graph")
     }
-    result
+    Ok(sorted_blocks)
 }
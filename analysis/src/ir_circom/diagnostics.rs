@@ -0,0 +1,125 @@
+use std::fmt;
+
+/// A construct of the IR that cannot be translated, with the place it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslationError {
+    /// Demangled path of the function.
+    pub function: String,
+    /// Basic block of the construct, `None` for errors concerning the whole function.
+    pub block: Option<String>,
+    /// Text of the instruction (or terminator, or parameter) that cannot be translated.
+    pub instruction: Option<String>,
+    pub message: String,
+}
+
+/// Printed like a compiler diagnostic:
+///
+/// ```text
+/// error: Dynamically sized alloca: %3 = alloca i8, i64 %n
+///   --> stylus_contract::SimpleContract::mock_check, block %start
+///    |
+///    |     %3 = alloca i8, i64 %n
+/// ```
+impl fmt::Display for TranslationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+        match &self.block {
+            Some(block) => write!(f, "  --> {}, block {block}", self.function)?,
+            None => write!(f, "  --> {}", self.function)?,
+        }
+        if let Some(instruction) = &self.instruction {
+            write!(f, "\n   |\n   |     {instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the errors of a translation, so that all the unsupported constructs are reported
+/// in one run.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    errors: Vec<TranslationError>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, error: TranslationError) {
+        // Unrolled loops repeat their instructions.
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+    pub fn extend(&mut self, other: Diagnostics) {
+        for error in other.errors {
+            self.push(error);
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+    pub fn errors(&self) -> &[TranslationError] {
+        &self.errors
+    }
+}
+
+impl From<TranslationError> for Diagnostics {
+    fn from(error: TranslationError) -> Self {
+        Self {
+            errors: vec![error],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in &self.errors {
+            writeln!(f, "{error}\n")?;
+        }
+        let plural = if self.errors.len() == 1 { "" } else { "s" };
+        write!(
+            f,
+            "error: aborting due to {} error{plural}",
+            self.errors.len()
+        )
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Result of translating a construct of the IR.
+pub type TranslationResult<T> = Result<T, TranslationError>;
+
+impl TranslationError {
+    /// An error whose location is filled in by [`TranslationError::at`] once it reaches the
+    /// loop walking the instructions.
+    pub fn unsupported(message: String) -> Self {
+        Self {
+            function: String::new(),
+            block: None,
+            instruction: None,
+            message,
+        }
+    }
+    /// Sets the location of the error, keeping the one it was raised with if any.
+    pub fn at(
+        mut self,
+        function: &str,
+        block: Option<String>,
+        instruction: Option<String>,
+    ) -> Self {
+        if self.function.is_empty() {
+            self.function = function.to_string();
+            self.block = block;
+            self.instruction = instruction;
+        }
+        self
+    }
+}
+
+/// Returns a [`TranslationError`] from the current function because the IR uses a construct
+/// that is not supported.
+macro_rules! unsupported {
+    ($($arg:tt)*) => {
+        return Err($crate::ir_circom::diagnostics::TranslationError::unsupported(format!($($arg)*)))
+    };
+}
+pub(crate) use unsupported;
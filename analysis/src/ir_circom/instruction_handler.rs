@@ -1,102 +1,500 @@
-use crate::{instructions::*, ir_circom::Structure};
-use llvm_ir::{Instruction, Terminator, terminator::Ret};
-
-// This is very not production-like but works for the purpose of this simple showcase
-const SKIPP_CALLS: &[&str] = &["spill", "precondition_check"];
+use crate::{
+    circom_codegen::CircomCodeGenerator,
+    instructions::*,
+    ir_circom::{
+        CallAction, InstructionConsumer, Structure,
+        control_flow::switch_case_signal,
+        diagnostics::{TranslationResult, unsupported},
+        function_to_circom,
+        intrinsics::{callee_name, handle_intrinsic, is_debug_intrinsic, is_intrinsic},
+        memory::{
+            handle_alloca_instruction, handle_gep_instruction, handle_load_instruction,
+            handle_pointer_parameter, handle_store_instruction, uses_value,
+        },
+        signal_declarations::SignalDeclarations,
+        template_name,
+        wide::{
+            LIMB_BITS, handle_wide_instruction, is_wide, limb_bits, limb_fields, operand_limbs,
+            operand_limbs_in, value_signals,
+        },
+    },
+};
+use llvm_ir::{Instruction, IntPredicate, Terminator, function::Parameter, terminator::Ret};
 
 pub fn handle_instruction(
     structure: &mut Structure,
     condition: &Option<CircomOperand>,
     instruction: &llvm_ir::Instruction,
-) -> Vec<CircomInstr> {
+) -> TranslationResult<Vec<CircomInstr>> {
+    if let Some(instrs) = handle_wide_instruction(structure, condition, instruction)? {
+        return Ok(instrs);
+    }
     match instruction {
-        Instruction::Mul(mul) => handle_mul_instruction(structure, mul),
-        Instruction::Add(add) => handle_add_instruction(structure, add),
+        Instruction::Mul(mul) => handle_mul_instruction(structure, condition, mul),
+        Instruction::Add(add) => handle_add_instruction(structure, condition, add),
+        Instruction::Sub(sub) => handle_sub_instruction(structure, condition, sub),
+        Instruction::UDiv(udiv) => handle_udiv_instruction(structure, condition, udiv),
+        Instruction::SDiv(sdiv) => handle_sdiv_instruction(structure, condition, sdiv),
+        Instruction::URem(urem) => handle_urem_instruction(structure, condition, urem),
+        Instruction::SRem(srem) => handle_srem_instruction(structure, condition, srem),
+        Instruction::And(and) => handle_and_instruction(structure, and),
+        Instruction::Or(or) => handle_or_instruction(structure, or),
+        Instruction::Xor(xor) => handle_xor_instruction(structure, xor),
+        Instruction::Shl(shl) => handle_shl_instruction(structure, condition, shl),
+        Instruction::LShr(lshr) => handle_lshr_instruction(structure, condition, lshr),
+        Instruction::AShr(ashr) => handle_ashr_instruction(structure, condition, ashr),
         Instruction::ICmp(icmp) => handle_icmp_instruction(structure, icmp),
         Instruction::ZExt(zext) => handle_zext_instruction(structure, zext),
-        // Drop the debug info and overflowing checks
-        skipp if SKIPP_CALLS.iter().any(|c| skipp.to_string().contains(c)) => vec![],
+        Instruction::SExt(sext) => handle_sext_instruction(structure, sext),
+        Instruction::Call(call) => handle_call_instruction(structure, condition, call),
+        Instruction::ExtractValue(extract) => handle_extractvalue_instruction(structure, extract),
         Instruction::Alloca(alloca) => handle_alloca_instruction(structure, alloca),
+        Instruction::GetElementPtr(gep) => handle_gep_instruction(structure, gep),
         Instruction::Store(store) => handle_store_instruction(structure, condition, store),
-        Instruction::Load(load) => handle_load_instruction(structure, load),
+        Instruction::Load(load) => handle_load_instruction(structure, condition, load),
         Instruction::Trunc(trunc) => handle_trunc_instruction(structure, trunc),
-        other => unimplemented!("{other}"),
+        Instruction::Phi(phi) => handle_phi_instruction(structure, phi),
+        Instruction::Select(select) => handle_select_instruction(structure, select),
+        other => unsupported!("{other}"),
     }
 }
 
-fn handle_alloca_instruction(
+/// Constrains a function input to the values its LLVM type admits: `i1` inputs must be
+/// boolean and `iN` inputs must fit in N bits. Extension attributes (`zeroext`, `signext`)
+/// do not widen that range, since the signal carries the N-bit value itself.
+/// Pointer parameters of a known size become input arrays, see [`handle_pointer_parameter`].
+pub fn handle_parameter(
     structure: &mut Structure,
-    alloca: &llvm_ir::instruction::Alloca,
-) -> Vec<CircomInstr> {
-    let name = alloca.dest.to_simple_string();
-    handle_alloca(structure, name)
+    parameter: &Parameter,
+) -> TranslationResult<Vec<CircomInstr>> {
+    if let llvm_ir::Type::PointerType { .. } = parameter.ty.as_ref() {
+        return handle_pointer_parameter(structure, parameter);
+    }
+    let Some(bits) = parameter.ty.integer_bits() else {
+        return Ok(vec![]);
+    };
+    let name = parameter.name.to_simple_string();
+    if is_wide(bits) {
+        let mut instrs = vec![];
+        for (i, limb) in value_signals(&name, bits).into_iter().enumerate() {
+            let input = structure.signals.get_reference(limb.clone());
+            let width = limb_bits(bits, i as u32);
+            instrs.extend(range_constraint(format!("{limb}_RANGE"), input, width));
+        }
+        return Ok(instrs);
+    }
+    let input = structure.signals.get_reference(name.clone());
+    Ok(range_constraint(format!("{name}_RANGE"), input, bits))
+}
+
+/// Constrains `input` to be an `iN` value.
+pub fn range_constraint(component: String, input: Reference, bits: u32) -> Vec<CircomInstr> {
+    let input = CircomOperand::Reference(input);
+    if bits == 1 {
+        let i = ConstraintEquality {
+            left: Expression::BinaryOperation(BinaryOperation {
+                left: input.clone(),
+                op: BinaryOperationType::Mul,
+                right: input.clone(),
+            }),
+            right: Expression::Operand(input),
+        };
+        return vec![i.into()];
+    }
+    let component = ComponentInstatiation {
+        name: component,
+        component: "Num2Bits".to_string(),
+        args: vec![bits],
+    };
+    let i = ConstraintGenerationAssigment {
+        left: component.field("in"),
+        right: Expression::Operand(input),
+    };
+    vec![component.into(), i.into()]
 }
 
-pub fn handle_alloca(structure: &mut Structure, name: String) -> Vec<CircomInstr> {
-    let dest = structure.signals.declare_mutable_reference(name);
+pub fn handle_alloca(
+    structure: &mut Structure,
+    name: String,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let dest = structure.signals.declare_mutable_reference(name)?;
     let i = ConstraintGenerationAssigment {
         left: dest,
-        right: Expression::Operand(CircomOperand::Constant(0)),
+        right: Expression::Operand(CircomOperand::constant(0u32, 1)),
+    };
+    Ok(vec![i.into()])
+}
+
+/// Translates a call the way the call policy maps its callee to.
+fn handle_call_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &llvm_ir::instruction::Call,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some(name) = callee_name(call) else {
+        unsupported!("Indirect calls are not supported: {call}");
+    };
+    // Debug info has no meaning in the circuit, whatever the policy says.
+    if is_debug_intrinsic(&name) {
+        return Ok(vec![]);
+    }
+    match structure.options.call_policy.action(&name) {
+        CallAction::Ignore => {
+            let function = structure.function_stack[0].clone();
+            structure.report.ignore_call(function, call);
+            Ok(vec![])
+        }
+        CallAction::Reject => {
+            unsupported!("Calls to {name} are rejected by the call policy: {call}")
+        }
+        CallAction::Component {
+            name: component,
+            args,
+            ..
+        } => handle_component_call(structure, call, component, args),
+        CallAction::Inline if is_intrinsic(&name) => {
+            unsupported!("Intrinsics cannot be inlined: {call}")
+        }
+        CallAction::Inline => handle_inlined_call(structure, condition, call, name),
+        CallAction::Template if is_intrinsic(&name) => {
+            handle_intrinsic(structure, condition, call, &name)
+        }
+        CallAction::Template => handle_function_call(structure, condition, call, name),
+    }
+}
+
+/// Name of the component (or the prefix of the inlined signals) standing for `call`.
+fn call_name(
+    structure: &Structure,
+    call: &llvm_ir::instruction::Call,
+    suffix: &str,
+) -> TranslationResult<String> {
+    let name = match &call.dest {
+        Some(dest) => format!("{}_{suffix}", structure.value_name(dest)?),
+        None => format!("{suffix}_{}", structure.callees.len() + structure.inlined),
     };
-    vec![i.into()]
+    Ok(name)
 }
 
+/// Instantiates the component the call policy maps the callee to. The arguments are wired to
+/// `in` (`in[i]` when there are several) and the result to `out`, integers wider than
+/// [`LIMB_BITS`](crate::ir_circom::wide::LIMB_BITS) as the arrays of their limbs.
+fn handle_component_call(
+    structure: &mut Structure,
+    call: &llvm_ir::instruction::Call,
+    component: String,
+    args: Vec<u32>,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let component = ComponentInstatiation {
+        name: call_name(structure, call, "CALL")?,
+        component,
+        args,
+    };
+    let mut instrs = vec![];
+    for (i, (argument, _)) in call.arguments.iter().enumerate() {
+        let Some(bits) = argument.integer_bits() else {
+            unsupported!("Passing non-integer arguments is not supported: {call}");
+        };
+        let field = match call.arguments.len() {
+            1 => "in".to_string(),
+            _ => format!("in[{i}]"),
+        };
+        let limbs = operand_limbs(structure, argument)?;
+        for (field, limb) in limb_fields(&field, bits).into_iter().zip(limbs) {
+            let i = ConstraintGenerationAssigment {
+                left: component.field(&field),
+                right: Expression::Operand(limb),
+            };
+            instrs.push(i.into());
+        }
+    }
+    if let Some(dest) = &call.dest {
+        let Some(bits) = structure.module.type_of(call).integer_bits() else {
+            unsupported!("Returning non-integer values is not supported: {call}");
+        };
+        let dest = structure.value_name(dest)?;
+        for (limb, field) in value_signals(&dest, bits)
+            .into_iter()
+            .zip(limb_fields("out", bits))
+        {
+            let res = ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(limb),
+                right: Expression::Operand(CircomOperand::Reference(component.field(&field))),
+            };
+            instrs.push(res.into());
+        }
+    }
+    instrs.insert(0, component.into());
+    Ok(instrs)
+}
+
+/// Splices the constraints of the called function into the current template, its signals and
+/// components being prefixed with the name of the call. The inputs of the callee become
+/// signals assigned from the arguments.
+fn handle_inlined_call(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &llvm_ir::instruction::Call,
+    callee: String,
+) -> TranslationResult<Vec<CircomInstr>> {
+    if structure.function_stack.contains(&callee) {
+        unsupported!("Recursive call to {callee} cannot be inlined: {call}");
+    }
+    let Some(function) = structure.module.get_func_by_name(&callee) else {
+        unsupported!("{callee} is only declared, its body is not available: {call}");
+    };
+    let inlined = function_to_circom(
+        template_name(&callee),
+        function,
+        structure.module,
+        structure.options,
+        true,
+        &structure.function_stack,
+    );
+    let prefix = format!("{}_", call_name(structure, call, "INL")?);
+    structure.inlined += 1;
+    let mut instrs = vec![];
+    let enabled = format!("{prefix}{}", structure.signals.enabled_signal_name());
+    let enabled = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(enabled),
+        right: Expression::Operand(enabled_operand(condition)),
+    };
+    instrs.push(enabled.into());
+    for (parameter, (argument, _)) in function.parameters.iter().zip(&call.arguments) {
+        if !SignalDeclarations::is_input(parameter) {
+            if uses_value(function, &parameter.name) {
+                unsupported!("Passing pointers to called functions is not supported: {call}");
+            }
+            continue;
+        }
+        let input = format!("{prefix}{}", parameter.name.to_simple_string());
+        let bits = parameter.ty.integer_bits().unwrap_or(0);
+        let limbs = operand_limbs(structure, argument)?;
+        for (input, limb) in value_signals(&input, bits).into_iter().zip(limbs) {
+            let i = ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(input),
+                right: Expression::Operand(limb),
+            };
+            instrs.push(i.into());
+        }
+    }
+    for instr in &inlined.template.instructions {
+        match instr.prefixed(&prefix) {
+            CircomInstr::SignalDeclaration(declaration) => {
+                let Reference::SignalRef(name) = declaration.reference() else {
+                    unreachable!();
+                };
+                structure.signals.get_reference(name);
+            }
+            other => instrs.push(other),
+        }
+    }
+    if let Some(dest) = &call.dest {
+        let Some(bits) = function.return_type.integer_bits() else {
+            unsupported!("Returning non-integer values is not supported: {call}");
+        };
+        let dest = structure.value_name(dest)?;
+        let output = format!("{prefix}{}", structure.signals.output_signal_name());
+        for (limb, output) in value_signals(&dest, bits)
+            .into_iter()
+            .zip(value_signals(&output, bits))
+        {
+            let res = ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(limb),
+                right: Expression::Operand(CircomOperand::Reference(Reference::SignalRef(output))),
+            };
+            instrs.push(res.into());
+        }
+    }
+    structure.callees.extend(inlined.callees);
+    structure.report.extend(inlined.report);
+    structure.diagnostics.extend(inlined.diagnostics);
+    Ok(instrs)
+}
+
+/// Instantiates the template of the called function, wiring the arguments to its inputs and
+/// its `OUTPUT_` to the result. The callee is only `ENABLED_` when the current block is executed.
+fn handle_function_call(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &llvm_ir::instruction::Call,
+    callee: String,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some(function) = structure.module.get_func_by_name(&callee) else {
+        unsupported!("{callee} is only declared, its body is not available: {call}");
+    };
+    let component = ComponentInstatiation {
+        name: call_name(structure, call, "CALL")?,
+        component: template_name(&callee),
+        args: vec![],
+    };
+    let mut instrs = vec![];
+    for (parameter, (argument, _)) in function.parameters.iter().zip(&call.arguments) {
+        if !SignalDeclarations::is_input(parameter) {
+            if uses_value(function, &parameter.name) {
+                unsupported!("Passing pointers to called functions is not supported: {call}");
+            }
+            continue;
+        }
+        let input = parameter.name.to_simple_string();
+        let bits = parameter.ty.integer_bits().unwrap_or(0);
+        let limbs = operand_limbs(structure, argument)?;
+        for (input, limb) in value_signals(&input, bits).into_iter().zip(limbs) {
+            let i = ConstraintGenerationAssigment {
+                left: component.field(&input),
+                right: Expression::Operand(limb),
+            };
+            instrs.push(i.into());
+        }
+    }
+    let enabled = ConstraintGenerationAssigment {
+        left: component.field(&structure.signals.enabled_signal_name()),
+        right: Expression::Operand(enabled_operand(condition)),
+    };
+    instrs.push(enabled.into());
+    if let Some(dest) = &call.dest {
+        let Some(bits) = function.return_type.integer_bits() else {
+            unsupported!("Returning non-integer values is not supported: {call}");
+        };
+        let dest = structure.value_name(dest)?;
+        let output = structure.signals.output_signal_name();
+        for (limb, output) in value_signals(&dest, bits)
+            .into_iter()
+            .zip(value_signals(&output, bits))
+        {
+            let res = ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(limb),
+                right: Expression::Operand(CircomOperand::Reference(component.field(&output))),
+            };
+            instrs.push(res.into());
+        }
+    }
+    instrs.insert(0, component.into());
+    structure.callees.push(callee);
+    Ok(instrs)
+}
+
+/// Signal holding the field of the aggregate (struct or array) value `aggregate`
+/// found at `indices`.
+pub fn aggregate_field(aggregate: &str, indices: &[u32]) -> String {
+    let indices: Vec<String> = indices.iter().map(|i| i.to_string()).collect();
+    format!("{aggregate}_AGG{}", indices.join("_"))
+}
+
+fn handle_extractvalue_instruction(
+    structure: &mut Structure,
+    extract: &llvm_ir::instruction::ExtractValue,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let aggregate = match &extract.aggregate {
+        aggregate @ llvm_ir::Operand::LocalOperand { .. } => structure.local_name(aggregate)?,
+        other => unsupported!("extractvalue from a constant aggregate: {other}"),
+    };
+    let field = aggregate_field(&aggregate, &extract.indices);
+    let dest = structure.value_name(&extract.dest)?;
+    let bits = structure
+        .module
+        .type_of(extract)
+        .integer_bits()
+        .unwrap_or(0);
+    value_signals(&dest, bits)
+        .into_iter()
+        .zip(value_signals(&field, bits))
+        .map(|(dest, field)| {
+            let field = structure.signals.get_reference(field);
+            let i = ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(dest),
+                right: Expression::Operand(CircomOperand::Reference(field)),
+            };
+            Ok(i.into())
+        })
+        .collect()
+}
+
+/// Zero extension keeps the value of the integer, only its type changes.
 fn handle_zext_instruction(
     structure: &mut Structure,
     zext: &llvm_ir::instruction::ZExt,
-) -> Vec<CircomInstr> {
+) -> TranslationResult<Vec<CircomInstr>> {
     let dest = structure
         .signals
-        .get_reference(zext.dest.to_simple_string());
+        .get_reference(structure.value_name(&zext.dest)?);
     let i = ConstraintGenerationAssigment {
         left: dest,
-        right: Expression::Operand(CircomOperand::from(&zext.operand)),
+        right: Expression::Operand(structure.operand(&zext.operand)?),
     }
     .into();
-    vec![i]
+    Ok(vec![i])
 }
 
 fn handle_trunc_instruction(
     structure: &mut Structure,
     trunc: &llvm_ir::instruction::Trunc,
-) -> Vec<CircomInstr> {
-    let dest = structure
-        .signals
-        .get_reference(trunc.dest.to_simple_string());
-    let i = ConstraintGenerationAssigment {
-        left: dest,
-        right: Expression::Operand(CircomOperand::from(&trunc.operand)),
-    }
-    .into();
-    vec![i]
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_conversion(
+        structure,
+        ("Trunc", "TRUNC"),
+        &trunc.dest,
+        &trunc.operand,
+        &trunc.to_type,
+    )
 }
 
-fn handle_store_instruction(
+fn handle_sext_instruction(
     structure: &mut Structure,
-    condition: &Option<CircomOperand>,
-    store: &llvm_ir::instruction::Store,
-) -> Vec<CircomInstr> {
-    handle_store(
+    sext: &llvm_ir::instruction::SExt,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_conversion(
         structure,
-        condition,
-        store.address.to_simple_string(),
-        CircomOperand::from(&store.value),
+        ("SignExtend", "SEXT"),
+        &sext.dest,
+        &sext.operand,
+        &sext.to_type,
     )
 }
 
+/// Lowers an integer conversion to its `circuit/llvm/conversions.circom` template,
+/// parametrized with the source and the destination bit widths.
+fn handle_conversion(
+    structure: &mut Structure,
+    (component, suffix): (&str, &str),
+    dest: &llvm_ir::Name,
+    operand: &llvm_ir::Operand,
+    to_type: &llvm_ir::TypeRef,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let name = structure.value_name(dest)?;
+    let (from, to) = match (operand.integer_bits(), to_type.integer_bits()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => unsupported!("{component} on non-integer operands"),
+    };
+    let component = ComponentInstatiation {
+        name: format!("{name}_{suffix}"),
+        component: component.to_string(),
+        args: vec![from, to],
+    };
+    let input = ConstraintGenerationAssigment {
+        left: component.field("in"),
+        right: Expression::Operand(structure.operand(operand)?),
+    };
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(name),
+        right: Expression::Operand(CircomOperand::Reference(component.field("out"))),
+    };
+    Ok(vec![component.into(), input.into(), res.into()])
+}
+
 pub fn handle_store(
     structure: &mut Structure,
     condition: &Option<CircomOperand>,
     name: String,
     value: CircomOperand,
-) -> Vec<CircomInstr> {
-    let prev = structure.signals.read_mutable_reference(name.clone());
-    let dest = structure.signals.increment_mutable_reference(name);
+) -> TranslationResult<Vec<CircomInstr>> {
+    let prev = structure.signals.read_mutable_reference(name.clone())?;
+    let dest = structure.signals.increment_mutable_reference(name)?;
     let intermediate_dest = match condition {
         None => dest.clone(),
         Some(_) => structure
             .signals
-            .get_reference(dest.intermediate_optional()),
+            .get_reference(dest.intermediate_optional()?),
     };
     let i = ConstraintGenerationAssigment {
         left: intermediate_dest.clone(),
@@ -104,117 +502,668 @@ pub fn handle_store(
     }
     .into();
     match condition {
-        None => vec![i],
-        Some(cond) => {
-            vec![
-                i,
+        None => Ok(vec![i]),
+        Some(cond) => Ok(vec![
+            i,
+            ConstraintGenerationAssigment {
+                left: dest,
+                right: Expression::Conditional(ConditionalValue {
+                    cond: cond.clone(),
+                    v_if_true: CircomOperand::Reference(intermediate_dest),
+                    v_if_false: prev,
+                }),
+            }
+            .into(),
+        ]),
+    }
+}
+
+/// Selects the incoming value of the edge that was taken. The edges into a block are mutually
+/// exclusive, so the values are folded into a chain of muxes on the edge conditions. Inside an
+/// unrolled loop every incoming block may have several instances, each edge reads the value
+/// computed by the iteration it comes from.
+fn handle_phi_instruction(
+    structure: &mut Structure,
+    phi: &llvm_ir::instruction::Phi,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some(bits) = phi.to_type.integer_bits() else {
+        unsupported!("phi of a non-integer type: {phi}");
+    };
+    let name = structure.value_name(&phi.dest)?;
+    let mut instrs = InstructionConsumer::default();
+    let mut edges: Vec<(Vec<CircomOperand>, llvm_ir::Name)> = vec![];
+    for (incoming, block) in &phi.incoming_values {
+        for parent in structure.parent_instances(block) {
+            edges.push((operand_limbs_in(structure, incoming, &parent)?, parent));
+        }
+    }
+    let ((last, _), rest) = match edges.split_last() {
+        Some(edges) => edges,
+        None => unsupported!("phi without reachable incoming blocks: {phi}"),
+    };
+    let targets = value_signals(&name, bits);
+    let mut values = last.clone();
+    for (i, (incoming, parent)) in rest.iter().enumerate().rev() {
+        let Some(cond) = structure.edge_condition(parent, &mut instrs)? else {
+            values = incoming.clone();
+            continue;
+        };
+        for ((target, value), incoming) in targets.iter().zip(values.iter_mut()).zip(incoming) {
+            let selected = structure.signals.get_reference(format!("{target}_PHI{i}"));
+            instrs.push(
                 ConstraintGenerationAssigment {
-                    left: dest,
+                    left: selected.clone(),
                     right: Expression::Conditional(ConditionalValue {
                         cond: cond.clone(),
-                        v_if_true: CircomOperand::Reference(intermediate_dest),
-                        v_if_false: prev,
+                        v_if_true: incoming.clone(),
+                        v_if_false: value.clone(),
                     }),
                 }
                 .into(),
-            ]
+            )?;
+            *value = CircomOperand::Reference(selected);
         }
     }
+    for (target, value) in targets.into_iter().zip(values) {
+        instrs.push(
+            ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(target),
+                right: Expression::Operand(value),
+            }
+            .into(),
+        )?;
+    }
+    Ok(instrs.instructions())
 }
 
-fn handle_load_instruction(
+/// A `select` only needs a single mux; `i1` selects that encode a boolean and/or
+/// (`select c, true, b` and `select c, b, false`) use the cheaper boolean formulas.
+fn handle_select_instruction(
     structure: &mut Structure,
-    load: &llvm_ir::instruction::Load,
-) -> Vec<CircomInstr> {
-    let dest = structure
-        .signals
-        .get_reference(load.dest.to_simple_string());
-    let source = structure
-        .signals
-        .read_mutable_reference(load.address.to_simple_string());
-    let i = ConstraintGenerationAssigment {
-        left: dest,
-        right: Expression::Operand(source),
+    select: &llvm_ir::instruction::Select,
+) -> TranslationResult<Vec<CircomInstr>> {
+    if select.condition.integer_bits() != Some(1) || select.true_value.integer_bits().is_none() {
+        unsupported!("select on non-scalar operands: {select}");
     }
-    .into();
-    vec![i]
+    let cond = structure.operand(&select.condition)?;
+    if let Some(bits) = select
+        .true_value
+        .integer_bits()
+        .filter(|bits| is_wide(*bits))
+    {
+        let dest = structure.value_name(&select.dest)?;
+        let v_if_true = operand_limbs(structure, &select.true_value)?;
+        let v_if_false = operand_limbs(structure, &select.false_value)?;
+        return Ok(value_signals(&dest, bits)
+            .into_iter()
+            .zip(v_if_true.into_iter().zip(v_if_false))
+            .map(|(limb, (v_if_true, v_if_false))| {
+                ConstraintGenerationAssigment {
+                    left: structure.signals.get_reference(limb),
+                    right: Expression::Conditional(ConditionalValue {
+                        cond: cond.clone(),
+                        v_if_true,
+                        v_if_false,
+                    }),
+                }
+                .into()
+            })
+            .collect());
+    }
+    let v_if_true = structure.operand(&select.true_value)?;
+    let v_if_false = structure.operand(&select.false_value)?;
+    let right = match (select.true_value.integer_bits(), &v_if_true, &v_if_false) {
+        (Some(1), t, _) if t.is_constant(1) => Expression::BinaryOr(BinaryOr {
+            a: cond,
+            b: v_if_false,
+        }),
+        (Some(1), _, f) if f.is_constant(0) => Expression::BinaryOperation(BinaryOperation {
+            left: cond,
+            op: BinaryOperationType::Mul,
+            right: v_if_true,
+        }),
+        _ => Expression::Conditional(ConditionalValue {
+            cond,
+            v_if_true,
+            v_if_false,
+        }),
+    };
+    let i = ConstraintGenerationAssigment {
+        left: structure
+            .signals
+            .get_reference(structure.value_name(&select.dest)?),
+        right,
+    };
+    Ok(vec![i.into()])
 }
 
 fn handle_mul_instruction(
     structure: &mut Structure,
+    condition: &Option<CircomOperand>,
     mul: &llvm_ir::instruction::Mul,
-) -> Vec<CircomInstr> {
-    let dest = structure.signals.get_reference(mul.dest.to_simple_string());
-    let i = ConstraintGenerationAssigment {
-        left: dest,
-        right: Expression::BinaryOperation(BinaryOperation {
-            left: CircomOperand::from(&mul.operand0),
-            op: BinaryOperationType::Mul,
-            right: CircomOperand::from(&mul.operand1),
-        }),
-    }
-    .into();
-    vec![i]
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("IntMul", "MUL", "out"),
+        &mul.dest,
+        (&mul.operand0, &mul.operand1),
+        &[mul.nuw, mul.nsw],
+    )
 }
 
 fn handle_add_instruction(
     structure: &mut Structure,
+    condition: &Option<CircomOperand>,
     add: &llvm_ir::instruction::Add,
-) -> Vec<CircomInstr> {
-    let dest = structure.signals.get_reference(add.dest.to_simple_string());
-    let i = ConstraintGenerationAssigment {
-        left: dest,
-        right: Expression::BinaryOperation(BinaryOperation {
-            left: CircomOperand::from(&add.operand0),
-            op: BinaryOperationType::Add,
-            right: CircomOperand::from(&add.operand1),
-        }),
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("IntAdd", "ADD", "out"),
+        &add.dest,
+        (&add.operand0, &add.operand1),
+        &[add.nuw, add.nsw],
+    )
+}
+
+fn handle_sub_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    sub: &llvm_ir::instruction::Sub,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("IntSub", "SUB", "out"),
+        &sub.dest,
+        (&sub.operand0, &sub.operand1),
+        &[sub.nuw, sub.nsw],
+    )
+}
+
+fn handle_udiv_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    udiv: &llvm_ir::instruction::UDiv,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("UDivRem", "DIV", "quotient"),
+        &udiv.dest,
+        (&udiv.operand0, &udiv.operand1),
+        &[udiv.exact],
+    )
+}
+
+fn handle_sdiv_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    sdiv: &llvm_ir::instruction::SDiv,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("SDivRem", "DIV", "quotient"),
+        &sdiv.dest,
+        (&sdiv.operand0, &sdiv.operand1),
+        &[sdiv.exact],
+    )
+}
+
+fn handle_urem_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    urem: &llvm_ir::instruction::URem,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("UDivRem", "REM", "remainder"),
+        &urem.dest,
+        (&urem.operand0, &urem.operand1),
+        &[false],
+    )
+}
+
+fn handle_srem_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    srem: &llvm_ir::instruction::SRem,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_arithmetic(
+        structure,
+        condition,
+        ("SDivRem", "REM", "remainder"),
+        &srem.dest,
+        (&srem.operand0, &srem.operand1),
+        &[false],
+    )
+}
+
+fn handle_and_instruction(
+    structure: &mut Structure,
+    and: &llvm_ir::instruction::And,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let (a, b) = (
+        structure.operand(&and.operand0)?,
+        structure.operand(&and.operand1)?,
+    );
+    let boolean = Expression::BinaryOperation(BinaryOperation {
+        left: a,
+        op: BinaryOperationType::Mul,
+        right: b,
+    });
+    handle_bitwise(
+        structure,
+        ("IntAnd", "AND"),
+        &and.dest,
+        (&and.operand0, &and.operand1),
+        boolean,
+    )
+}
+
+fn handle_or_instruction(
+    structure: &mut Structure,
+    or: &llvm_ir::instruction::Or,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let (a, b) = (
+        structure.operand(&or.operand0)?,
+        structure.operand(&or.operand1)?,
+    );
+    let boolean = Expression::BinaryOr(BinaryOr { a, b });
+    handle_bitwise(
+        structure,
+        ("IntOr", "OR"),
+        &or.dest,
+        (&or.operand0, &or.operand1),
+        boolean,
+    )
+}
+
+fn handle_xor_instruction(
+    structure: &mut Structure,
+    xor: &llvm_ir::instruction::Xor,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let (a, b) = (
+        structure.operand(&xor.operand0)?,
+        structure.operand(&xor.operand1)?,
+    );
+    let boolean = Expression::BinaryXor(BinaryXor { a, b });
+    handle_bitwise(
+        structure,
+        ("IntXor", "XOR"),
+        &xor.dest,
+        (&xor.operand0, &xor.operand1),
+        boolean,
+    )
+}
+
+/// `i1` operands are booleans already, so the `boolean` formula is used directly.
+/// Wider integers go through the bit decomposition of `circuit/llvm/bitwise.circom`.
+fn handle_bitwise(
+    structure: &mut Structure,
+    (component, suffix): (&str, &str),
+    dest: &llvm_ir::Name,
+    (operand0, operand1): (&llvm_ir::Operand, &llvm_ir::Operand),
+    boolean: Expression,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let name = structure.value_name(dest)?;
+    let bits = match operand0.integer_bits() {
+        Some(bits) => bits,
+        None => unsupported!("{component} on non-integer operands"),
+    };
+    if bits == 1 {
+        let i = ConstraintGenerationAssigment {
+            left: structure.signals.get_reference(name),
+            right: boolean,
+        };
+        return Ok(vec![i.into()]);
     }
-    .into();
-    vec![i]
+    let component = ComponentInstatiation {
+        name: format!("{name}_{suffix}"),
+        component: component.to_string(),
+        args: vec![bits],
+    };
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(name),
+        right: Expression::Operand(CircomOperand::Reference(component.field("out"))),
+    };
+    let mut instrs = binary_component(
+        component,
+        structure.operand(operand0)?,
+        structure.operand(operand1)?,
+    );
+    instrs.push(res.into());
+    Ok(instrs)
+}
+
+fn handle_shl_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    shl: &llvm_ir::instruction::Shl,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_shift(
+        structure,
+        condition,
+        ("Shl", "SHL"),
+        &shl.dest,
+        (&shl.operand0, &shl.operand1),
+        &[shl.nuw, shl.nsw],
+    )
+}
+
+fn handle_lshr_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    lshr: &llvm_ir::instruction::LShr,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_shift(
+        structure,
+        condition,
+        ("LShr", "SHR"),
+        &lshr.dest,
+        (&lshr.operand0, &lshr.operand1),
+        &[lshr.exact],
+    )
+}
+
+fn handle_ashr_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    ashr: &llvm_ir::instruction::AShr,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_shift(
+        structure,
+        condition,
+        ("AShr", "SHR"),
+        &ashr.dest,
+        (&ashr.operand0, &ashr.operand1),
+        &[ashr.exact],
+    )
+}
+
+/// Shifts by a constant amount only rewire the bits of the operand (`ShlConst` & co.),
+/// shifts by a variable amount multiply or divide by the matching power of two.
+fn handle_shift(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    (component, suffix): (&str, &str),
+    dest: &llvm_ir::Name,
+    (operand0, operand1): (&llvm_ir::Operand, &llvm_ir::Operand),
+    flags: &[bool],
+) -> TranslationResult<Vec<CircomInstr>> {
+    let amount = match structure.operand(operand1)? {
+        CircomOperand::Constant(amount) => amount,
+        _ => {
+            let shift = (component, suffix, "out");
+            return handle_arithmetic(
+                structure,
+                condition,
+                shift,
+                dest,
+                (operand0, operand1),
+                flags,
+            );
+        }
+    };
+    let name = structure.value_name(dest)?;
+    let bits = match operand0.integer_bits() {
+        Some(bits) => bits,
+        None => unsupported!("{component} on non-integer operands"),
+    };
+    let amount = match u32::try_from(&amount.value) {
+        Ok(amount) if amount < bits => amount,
+        _ => unsupported!("{component} of an i{bits} by {amount} bits is poison"),
+    };
+    let component = ComponentInstatiation {
+        name: format!("{name}_{suffix}"),
+        component: format!("{component}Const"),
+        args: [bits, amount]
+            .into_iter()
+            .chain(flags.iter().map(|f| *f as u32))
+            .collect(),
+    };
+    let input = ConstraintGenerationAssigment {
+        left: component.field("in"),
+        right: Expression::Operand(structure.operand(operand0)?),
+    };
+    let enabled = ConstraintGenerationAssigment {
+        left: component.field("enabled"),
+        right: Expression::Operand(enabled_operand(condition)),
+    };
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(name),
+        right: Expression::Operand(CircomOperand::Reference(component.field("out"))),
+    };
+    Ok(vec![
+        component.into(),
+        input.into(),
+        enabled.into(),
+        res.into(),
+    ])
+}
+
+/// Lowers a two's complement binary operation to its `circuit/llvm` template,
+/// passing the bit width and the instruction `flags` as the template arguments.
+/// The result is reduced modulo 2^N, or, with `nuw`/`nsw`, constrained not to wrap
+/// whenever the current block is executed. Likewise, division is only checked for a
+/// zero divisor (and `exact` for a zero remainder) on the executed path.
+fn handle_arithmetic(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    (component, suffix, output): (&str, &str, &str),
+    dest: &llvm_ir::Name,
+    (operand0, operand1): (&llvm_ir::Operand, &llvm_ir::Operand),
+    flags: &[bool],
+) -> TranslationResult<Vec<CircomInstr>> {
+    let name = structure.value_name(dest)?;
+    let bits = match operand0.integer_bits() {
+        Some(bits) => bits,
+        None => unsupported!("{component} on non-integer operands"),
+    };
+    let component = ComponentInstatiation {
+        name: format!("{name}_{suffix}"),
+        component: component.to_string(),
+        args: [bits]
+            .into_iter()
+            .chain(flags.iter().map(|f| *f as u32))
+            .collect(),
+    };
+    let enabled = ConstraintGenerationAssigment {
+        left: component.field("enabled"),
+        right: Expression::Operand(enabled_operand(condition)),
+    };
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(name),
+        right: Expression::Operand(CircomOperand::Reference(component.field(output))),
+    };
+    let mut instrs = binary_component(
+        component,
+        structure.operand(operand0)?,
+        structure.operand(operand1)?,
+    );
+    instrs.extend([enabled.into(), res.into()]);
+    Ok(instrs)
+}
+
+/// Signal telling whether the current block is executed, for constraints that may only
+/// hold on the executed path.
+pub fn enabled_operand(condition: &Option<CircomOperand>) -> CircomOperand {
+    condition
+        .clone()
+        .unwrap_or(CircomOperand::constant(1u32, 1))
 }
 
 fn handle_icmp_instruction(
     structure: &mut Structure,
     icmp: &llvm_ir::instruction::ICmp,
-) -> Vec<CircomInstr> {
-    let name = icmp.dest.to_simple_string();
-    let dest = structure.signals.get_reference(name.clone());
+) -> TranslationResult<Vec<CircomInstr>> {
+    let name = structure.value_name(&icmp.dest)?;
+    let bits = match icmp.operand0.integer_bits() {
+        Some(bits) => bits,
+        None => unsupported!("icmp on non-integer operands: {icmp}"),
+    };
+    // `ne` is lowered as the negation of `eq`, every other predicate has its own comparator.
+    let (component, args, negate) = match icmp.predicate {
+        IntPredicate::EQ => ("IsEqual", vec![], false),
+        IntPredicate::NE => ("IsEqual", vec![], true),
+        IntPredicate::ULT => ("LessThan", vec![bits], false),
+        IntPredicate::ULE => ("LessEqThan", vec![bits], false),
+        IntPredicate::UGT => ("GreaterThan", vec![bits], false),
+        IntPredicate::UGE => ("GreaterEqThan", vec![bits], false),
+        IntPredicate::SLT => ("SignedLessThan", vec![bits], false),
+        IntPredicate::SLE => ("SignedLessEqThan", vec![bits], false),
+        IntPredicate::SGT => ("SignedGreaterThan", vec![bits], false),
+        IntPredicate::SGE => ("SignedGreaterEqThan", vec![bits], false),
+    };
+    let suffix = if component == "IsEqual" { "EQ" } else { "CMP" };
     let component = ComponentInstatiation {
-        name: format! {"{}_EQ", name},
-        component: "IsEqual".to_string(),
+        name: format!("{name}_{suffix}"),
+        component: component.to_string(),
+        args,
     };
+    let out = CircomOperand::Reference(component.field("out"));
+    let mut instrs = binary_component(
+        component,
+        structure.operand(&icmp.operand0)?,
+        structure.operand(&icmp.operand1)?,
+    );
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(name),
+        right: if negate {
+            Expression::BinaryOperation(BinaryOperation {
+                left: CircomOperand::constant(1u32, 1),
+                op: BinaryOperationType::Sub,
+                right: out,
+            })
+        } else {
+            Expression::Operand(out)
+        },
+    };
+    instrs.push(res.into());
+    Ok(instrs)
+}
+
+/// Instantiates a component with the circomlib `in[2]` interface and wires both operands to it.
+pub fn binary_component(
+    component: ComponentInstatiation,
+    operand0: CircomOperand,
+    operand1: CircomOperand,
+) -> Vec<CircomInstr> {
     let x = ConstraintGenerationAssigment {
         left: component.field("in[0]"),
-        right: Expression::Operand(CircomOperand::from(&icmp.operand0)),
+        right: Expression::Operand(operand0),
     };
     let y = ConstraintGenerationAssigment {
         left: component.field("in[1]"),
-        right: Expression::Operand(CircomOperand::from(&icmp.operand1)),
+        right: Expression::Operand(operand1),
     };
-    let res = ConstraintGenerationAssigment {
-        left: dest,
-        right: Expression::Operand(CircomOperand::Reference(component.field("out"))),
-    };
-    vec![CircomInstr::from(component), x.into(), y.into(), res.into()]
+    vec![CircomInstr::from(component), x.into(), y.into()]
 }
 
 pub fn handle_return_terminator(
     structure: &mut Structure,
     condition: &Option<CircomOperand>,
     terminator: &Terminator,
-) -> Vec<CircomInstr> {
+) -> TranslationResult<Vec<CircomInstr>> {
     match terminator {
-        Terminator::Ret(Ret { return_operand, .. }) => {
-            let value = CircomOperand::from(return_operand.as_ref().unwrap());
-            handle_store(
-                structure,
-                condition,
-                structure.signals.output_signal_name(),
-                value,
-            )
+        Terminator::Ret(Ret {
+            return_operand: None,
+            ..
+        }) => Ok(vec![]),
+        Terminator::Ret(Ret {
+            return_operand: Some(return_operand),
+            ..
+        }) => {
+            let output = structure.signals.output_signal_name();
+            let bits = return_operand.integer_bits().unwrap_or(0);
+            let values = match is_wide(bits) {
+                true => operand_limbs(structure, return_operand)?,
+                false => vec![CircomOperand::try_from(return_operand)?],
+            };
+            let mut instrs = vec![];
+            for (name, value) in value_signals(&output, bits).into_iter().zip(values) {
+                instrs.extend(handle_store(structure, condition, name, value)?);
+            }
+            Ok(instrs)
         }
-        Terminator::Br(_) | Terminator::CondBr(_) => vec![],
-        t => unimplemented!("{t}"),
+        Terminator::Br(_) | Terminator::CondBr(_) => Ok(vec![]),
+        Terminator::Switch(switch) => handle_switch_terminator(structure, switch),
+        // Reaching `unreachable` is undefined behaviour, the block must never execute.
+        Terminator::Unreachable(_) => Ok(vec![
+            ConstraintEquality {
+                left: Expression::Operand(enabled_operand(condition)),
+                right: Expression::Operand(CircomOperand::constant(0u32, 1)),
+            }
+            .into(),
+        ]),
+        t => unsupported!("{t}"),
     }
 }
+
+/// Declares the signals the control flow analysis uses as the `switch` branch conditions:
+/// `value == case_i` for every case, and the negation of all of them for the default.
+fn handle_switch_terminator(
+    structure: &mut Structure,
+    switch: &llvm_ir::terminator::Switch,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let block = structure.current_block.clone();
+    // A switch on the limbs of a wide value would need every limb to match.
+    if let Some(bits) = switch.operand.integer_bits().filter(|b| is_wide(*b)) {
+        unsupported!("`switch` on an i{bits}, wider than {LIMB_BITS} bits");
+    }
+    let value = structure.operand(&switch.operand)?;
+    let mut instrs = vec![];
+    let mut cases = vec![];
+    for (i, (case, _)) in switch.dests.iter().enumerate() {
+        let taken = structure
+            .signals
+            .get_reference(switch_case_signal(&block, Some(i)));
+        let component = ComponentInstatiation {
+            name: format!("{}_EQ", taken.to_circom()),
+            component: "IsEqual".to_string(),
+            args: vec![],
+        };
+        let out = CircomOperand::Reference(component.field("out"));
+        instrs.extend(binary_component(
+            component,
+            value.clone(),
+            structure.operand(&llvm_ir::Operand::ConstantOperand(case.clone()))?,
+        ));
+        instrs.push(
+            ConstraintGenerationAssigment {
+                left: taken.clone(),
+                right: Expression::Operand(out),
+            }
+            .into(),
+        );
+        cases.push(CircomOperand::Reference(taken));
+    }
+    let default = structure
+        .signals
+        .get_reference(switch_case_signal(&block, None));
+    let any_case = structure
+        .signals
+        .get_reference(format!("{}_ANY", default.to_circom()));
+    instrs.push(
+        ConstraintGenerationAssigment {
+            left: any_case.clone(),
+            right: Expression::Sum(cases),
+        }
+        .into(),
+    );
+    instrs.push(
+        ConstraintGenerationAssigment {
+            left: default,
+            right: Expression::BinaryOperation(BinaryOperation {
+                left: CircomOperand::constant(1u32, 1),
+                op: BinaryOperationType::Sub,
+                right: CircomOperand::Reference(any_case),
+            }),
+        }
+        .into(),
+    );
+    Ok(instrs)
+}
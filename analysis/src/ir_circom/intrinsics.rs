@@ -0,0 +1,289 @@
+use llvm_ir::{Constant, Name, Operand, instruction::Call};
+
+use crate::{
+    instructions::*,
+    ir_circom::{
+        Structure,
+        diagnostics::{TranslationResult, unsupported},
+        instruction_handler::{aggregate_field, binary_component, enabled_operand},
+        memory::{handle_memcpy, handle_memset},
+        wide::{handle_wide_intrinsic, is_wide_call},
+    },
+};
+
+/// Lowers a call to an intrinsic with the `circuit/llvm` template it is mapped to.
+type IntrinsicHandler =
+    fn(&mut Structure, &Option<CircomOperand>, &Call, &str) -> TranslationResult<Vec<CircomInstr>>;
+
+/// Intrinsics are matched by name prefix, the rest of the name being the overloaded type
+/// suffix (`.i32`, ...).
+const INTRINSICS: &[(&str, &str, IntrinsicHandler)] = &[
+    (
+        "llvm.uadd.with.overflow.",
+        "AddWithOverflow",
+        handle_unsigned_with_overflow,
+    ),
+    (
+        "llvm.sadd.with.overflow.",
+        "AddWithOverflow",
+        handle_signed_with_overflow,
+    ),
+    (
+        "llvm.usub.with.overflow.",
+        "SubWithOverflow",
+        handle_unsigned_with_overflow,
+    ),
+    (
+        "llvm.ssub.with.overflow.",
+        "SubWithOverflow",
+        handle_signed_with_overflow,
+    ),
+    (
+        "llvm.umul.with.overflow.",
+        "MulWithOverflow",
+        handle_unsigned_with_overflow,
+    ),
+    (
+        "llvm.smul.with.overflow.",
+        "MulWithOverflow",
+        handle_signed_with_overflow,
+    ),
+    ("llvm.umin.", "UMin", handle_integer_intrinsic),
+    ("llvm.umax.", "UMax", handle_integer_intrinsic),
+    ("llvm.smin.", "SMin", handle_integer_intrinsic),
+    ("llvm.smax.", "SMax", handle_integer_intrinsic),
+    ("llvm.abs.", "Abs", handle_integer_intrinsic),
+    ("llvm.ctpop.", "Ctpop", handle_integer_intrinsic),
+    ("llvm.ctlz.", "Ctlz", handle_integer_intrinsic),
+    ("llvm.cttz.", "Cttz", handle_integer_intrinsic),
+    ("llvm.bswap.", "Bswap", handle_integer_intrinsic),
+    ("llvm.fshl.", "FunnelShift", handle_funnel_shift_left),
+    ("llvm.fshr.", "FunnelShift", handle_funnel_shift_right),
+];
+
+/// Lowers a call to a memory intrinsic by moving memory slots.
+type MemoryIntrinsicHandler =
+    fn(&mut Structure, &Option<CircomOperand>, &Call) -> TranslationResult<Vec<CircomInstr>>;
+
+/// Memory intrinsics only move slots, they have no template.
+const MEMORY_INTRINSICS: &[(&str, MemoryIntrinsicHandler)] = &[
+    ("llvm.memcpy.", handle_memcpy),
+    ("llvm.memmove.", handle_memcpy),
+    ("llvm.memset.", handle_memset),
+];
+
+/// Name of the function called directly by `call`, `None` for indirect calls.
+pub fn callee_name(call: &Call) -> Option<String> {
+    match call.function.as_ref().right()? {
+        Operand::ConstantOperand(c) => match c.as_ref() {
+            Constant::GlobalReference {
+                name: Name::Name(name),
+                ..
+            } => Some(name.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn is_intrinsic(name: &str) -> bool {
+    name.starts_with("llvm.")
+}
+
+/// `llvm.dbg.declare`, `llvm.dbg.value`, ... which only describe variables to the debugger.
+pub fn is_debug_intrinsic(name: &str) -> bool {
+    name.starts_with("llvm.dbg.")
+}
+
+pub fn handle_intrinsic(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+    name: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    if let Some((_, handler)) = MEMORY_INTRINSICS
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+    {
+        return handler(structure, condition, call);
+    }
+    if is_wide_call(structure, call) {
+        return handle_wide_intrinsic(structure, call, name);
+    }
+    match INTRINSICS
+        .iter()
+        .find(|(prefix, _, _)| name.starts_with(prefix))
+    {
+        Some((_, component, handler)) => handler(structure, condition, call, component),
+        None => {
+            let supported: Vec<String> = (INTRINSICS.iter().map(|(prefix, _, _)| prefix))
+                .chain(MEMORY_INTRINSICS.iter().map(|(prefix, _)| prefix))
+                .map(|prefix| format!("{prefix}*"))
+                .collect();
+            unsupported!(
+                "Intrinsic {name} has no lowering: {call}\nSupported intrinsics: {}",
+                supported.join(", ")
+            )
+        }
+    }
+}
+
+fn handle_unsigned_with_overflow(
+    structure: &mut Structure,
+    _condition: &Option<CircomOperand>,
+    call: &Call,
+    component: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_with_overflow(structure, call, component, false)
+}
+
+fn handle_signed_with_overflow(
+    structure: &mut Structure,
+    _condition: &Option<CircomOperand>,
+    call: &Call,
+    component: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_with_overflow(structure, call, component, true)
+}
+
+/// `llvm.{s,u}{add,sub,mul}.with.overflow` return the `{ iN, i1 }` pair of the wrapped result
+/// and the overflow flag, declared as the two fields of the destination aggregate.
+fn handle_with_overflow(
+    structure: &mut Structure,
+    call: &Call,
+    component: &str,
+    signed: bool,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let [(operand0, _), (operand1, _)] = &call.arguments[..] else {
+        unsupported!("{call}");
+    };
+    let Some(bits) = operand0.integer_bits() else {
+        unsupported!("{call}");
+    };
+    let Some(dest) = &call.dest else {
+        return Ok(vec![]);
+    };
+    let dest = structure.value_name(dest)?;
+    let component = ComponentInstatiation {
+        name: format!("{dest}_OVF"),
+        component: component.to_string(),
+        args: vec![bits, signed as u32],
+    };
+    let fields: Vec<CircomInstr> = ["out", "overflow"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, field)| {
+            ConstraintGenerationAssigment {
+                left: structure
+                    .signals
+                    .get_reference(aggregate_field(&dest, &[i as u32])),
+                right: Expression::Operand(CircomOperand::Reference(component.field(field))),
+            }
+            .into()
+        })
+        .collect();
+    let mut instrs = binary_component(
+        component,
+        structure.operand(operand0)?,
+        structure.operand(operand1)?,
+    );
+    instrs.extend(fields);
+    Ok(instrs)
+}
+
+fn handle_funnel_shift_left(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+    component: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_intrinsic_component(structure, condition, call, component, &[1])
+}
+
+fn handle_funnel_shift_right(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+    component: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_intrinsic_component(structure, condition, call, component, &[0])
+}
+
+fn handle_integer_intrinsic(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+    component: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    handle_intrinsic_component(structure, condition, call, component, &[])
+}
+
+/// Instantiates `component(N, args...)` for an intrinsic on `iN` values. The `iN` arguments
+/// are wired to `in` (`in[i]` when there are several), while the constant `i1` arguments of
+/// intrinsics overloaded on a wider type (the poison flags of `abs`, `ctlz` and `cttz`) are
+/// appended to the template arguments, with the component `enabled` on the current block.
+fn handle_intrinsic_component(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+    component: &str,
+    args: &[u32],
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some(bits) = call.arguments.first().and_then(|(a, _)| a.integer_bits()) else {
+        unsupported!("{call}");
+    };
+    let (inputs, flags): (Vec<_>, Vec<_>) = call
+        .arguments
+        .iter()
+        .map(|(a, _)| a)
+        .partition(|a| bits == 1 || a.integer_bits() == Some(bits));
+    let flags: Vec<u32> = flags
+        .into_iter()
+        .map(|flag| match structure.operand(flag)? {
+            CircomOperand::Constant(flag) => match u32::try_from(&flag.value) {
+                Ok(flag) => Ok(flag),
+                Err(_) => unsupported!("{call}"),
+            },
+            _ => unsupported!("{call}"),
+        })
+        .collect::<TranslationResult<_>>()?;
+    let Some(dest) = &call.dest else {
+        return Ok(vec![]);
+    };
+    let dest = structure.value_name(dest)?;
+    let component = ComponentInstatiation {
+        name: format!("{dest}_INTR"),
+        component: component.to_string(),
+        args: [bits]
+            .into_iter()
+            .chain(args.iter().copied())
+            .chain(flags.iter().copied())
+            .collect(),
+    };
+    let mut instrs = vec![];
+    for (i, input) in inputs.iter().enumerate() {
+        let field = match inputs.len() {
+            1 => "in".to_string(),
+            _ => format!("in[{i}]"),
+        };
+        let i = ConstraintGenerationAssigment {
+            left: component.field(&field),
+            right: Expression::Operand(structure.operand(input)?),
+        };
+        instrs.push(i.into());
+    }
+    if !flags.is_empty() {
+        let i = ConstraintGenerationAssigment {
+            left: component.field("enabled"),
+            right: Expression::Operand(enabled_operand(condition)),
+        };
+        instrs.push(i.into());
+    }
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(dest),
+        right: Expression::Operand(CircomOperand::Reference(component.field("out"))),
+    };
+    instrs.insert(0, component.into());
+    instrs.push(res.into());
+    Ok(instrs)
+}
@@ -0,0 +1,480 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use llvm_ir::{
+    BasicBlock, Constant, Instruction, IntPredicate, Name, Operand, Terminator,
+    terminator::Unreachable,
+};
+
+use crate::{
+    instructions::{IRIntegerBits, IRNameToSimpleString},
+    ir_circom::{
+        control_flow::{self, dominators},
+        diagnostics::{TranslationResult, unsupported},
+    },
+};
+
+/// Iterations simulated at most when inferring the trip count of a loop.
+const MAX_INFERRED_TRIP_COUNT: usize = 1 << 16;
+
+/// Iteration of every loop containing a block, outermost loop first.
+pub type Iterations = Vec<(Name, usize)>;
+
+/// A copy of a basic block for one iteration of the loops containing it.
+/// Blocks outside of any loop have a single instance named like the block.
+#[derive(Debug, Clone)]
+pub struct BlockInstance {
+    /// The block, renamed and with its terminator retargeted to other instances.
+    pub block: BasicBlock,
+    /// Name of the block in the function.
+    pub original: Name,
+    pub iterations: Iterations,
+}
+
+/// Knows which values are defined inside loops, and therefore get a signal per iteration.
+#[derive(Debug, Clone, Default)]
+pub struct LoopScopes {
+    defining_loops: HashMap<Name, Vec<Name>>,
+}
+
+impl LoopScopes {
+    /// Signal name of `value` as read from a block instance executing `iterations`.
+    pub fn value_name(
+        &self,
+        value: &Name,
+        iterations: &[(Name, usize)],
+    ) -> TranslationResult<String> {
+        let name = value.to_simple_string();
+        let Some(loops) = self.defining_loops.get(value) else {
+            return Ok(name);
+        };
+        let mut suffix = vec![];
+        for header in loops {
+            match iterations.iter().find(|(h, _)| h == header) {
+                Some((_, i)) => suffix.push(i.to_string()),
+                None => unsupported!("{value} is used outside of the loop defining it"),
+            }
+        }
+        Ok(format!("{name}_it{}", suffix.join("_")))
+    }
+
+    fn rename(
+        &self,
+        operand: &Operand,
+        iterations: &[(Name, usize)],
+    ) -> TranslationResult<Operand> {
+        let renamed = match operand {
+            Operand::LocalOperand { name, ty } if self.defining_loops.contains_key(name) => {
+                Operand::LocalOperand {
+                    name: Name::from(self.value_name(name, iterations)?),
+                    ty: ty.clone(),
+                }
+            }
+            other => other.clone(),
+        };
+        Ok(renamed)
+    }
+}
+
+pub struct UnrolledBlocks {
+    pub instances: Vec<BlockInstance>,
+    pub scopes: LoopScopes,
+}
+
+struct NaturalLoop {
+    header: Name,
+    body: HashSet<Name>,
+    latches: Vec<Name>,
+}
+
+/// Unrolls every natural loop of the function so that its control flow graph becomes acyclic.
+/// A loop is unrolled as many times as its inferred trip count, or `bound` when the trip count
+/// cannot be inferred. Taking the back edge one more time leads to an `unreachable` block, so
+/// a bound that is too small makes the circuit unsatisfiable instead of silently wrong.
+pub fn unroll_loops(
+    blocks: &[BasicBlock],
+    bound: Option<usize>,
+) -> TranslationResult<UnrolledBlocks> {
+    let Some(entry) = blocks.first().map(|b| b.name.clone()) else {
+        unsupported!("A function without blocks");
+    };
+    let by_name: HashMap<&Name, &BasicBlock> = blocks.iter().map(|b| (&b.name, b)).collect();
+    let successors: HashMap<Name, Vec<Name>> = blocks
+        .iter()
+        .map(|b| Ok((b.name.clone(), control_flow::successors(&b.term)?)))
+        .collect::<TranslationResult<_>>()?;
+    let dominators = dominators(&entry, &successors);
+    let loops = natural_loops(&successors, &dominators);
+
+    let mut bounds: HashMap<&Name, usize> = HashMap::new();
+    for l in &loops {
+        match infer_trip_count(l, blocks, &dominators).or(bound) {
+            Some(trip_count) => _ = bounds.insert(&l.header, trip_count),
+            None => unsupported!(
+                "Cannot infer the trip count of the loop at {}, pass --loop-bound",
+                l.header
+            ),
+        }
+    }
+
+    let mut defining_loops: HashMap<Name, Vec<Name>> = HashMap::new();
+    for block in blocks {
+        let headers: Vec<Name> = containing_loops(&loops, &block.name)
+            .map(|l| l.header.clone())
+            .collect();
+        if headers.is_empty() {
+            continue;
+        }
+        for instr in &block.instrs {
+            if let Some(dest) = instr.try_get_result() {
+                defining_loops.insert(dest.clone(), headers.clone());
+            }
+        }
+    }
+    let scopes = LoopScopes { defining_loops };
+
+    let mut instances = vec![];
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([(entry, Iterations::new())]);
+    while let Some((original, iterations)) = queue.pop_front() {
+        let name = instance_name(&original, &iterations);
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let mut targets = HashMap::new();
+        for to in &successors[&original] {
+            let target = match successor_iterations(&loops, &bounds, &original, &iterations, to) {
+                Ok(next) => {
+                    let target = instance_name(to, &next);
+                    queue.push_back((to.clone(), next));
+                    target
+                }
+                Err((header, outer)) => {
+                    let target = Name::from(format!(
+                        "{}_unroll_bound",
+                        instance_name(&header, &outer).to_simple_string()
+                    ));
+                    if seen.insert(target.clone()) {
+                        instances.push(BlockInstance {
+                            block: BasicBlock {
+                                name: target.clone(),
+                                instrs: vec![],
+                                term: Terminator::Unreachable(Unreachable { debugloc: None }),
+                            },
+                            original: header,
+                            iterations: outer,
+                        });
+                    }
+                    target
+                }
+            };
+            targets.insert(to.clone(), target);
+        }
+        let block = by_name[&original];
+        instances.push(BlockInstance {
+            block: BasicBlock {
+                name,
+                instrs: block.instrs.clone(),
+                term: retarget(&block.term, &targets, &scopes, &iterations)?,
+            },
+            original,
+            iterations,
+        });
+    }
+    Ok(UnrolledBlocks { instances, scopes })
+}
+
+fn instance_name(block: &Name, iterations: &Iterations) -> Name {
+    if iterations.is_empty() {
+        return block.clone();
+    }
+    let suffix: Vec<String> = iterations.iter().map(|(_, i)| i.to_string()).collect();
+    Name::from(format!(
+        "{}_it{}",
+        block.to_simple_string(),
+        suffix.join("_")
+    ))
+}
+
+/// Iterations of the instance of `to` reached from the instance of `from` executing `iterations`.
+/// Fails with the loop header (and the iterations of the enclosing loops) when the edge would
+/// start an iteration past the bound of the loop.
+fn successor_iterations(
+    loops: &[NaturalLoop],
+    bounds: &HashMap<&Name, usize>,
+    from: &Name,
+    iterations: &Iterations,
+    to: &Name,
+) -> Result<Iterations, (Name, Iterations)> {
+    let mut next = Iterations::new();
+    for l in containing_loops(loops, to) {
+        let i = match iterations.iter().find(|(h, _)| *h == l.header) {
+            // Taking the back edge starts the next iteration.
+            Some((_, i)) if l.body.contains(from) && *to == l.header => i + 1,
+            Some((_, i)) if l.body.contains(from) => *i,
+            _ => 0,
+        };
+        if i >= bounds[&l.header] {
+            return Err((l.header.clone(), next));
+        }
+        next.push((l.header.clone(), i));
+    }
+    Ok(next)
+}
+
+/// Loops containing `block`, outermost first.
+fn containing_loops<'a>(
+    loops: &'a [NaturalLoop],
+    block: &'a Name,
+) -> impl Iterator<Item = &'a NaturalLoop> {
+    loops.iter().filter(move |l| l.body.contains(block))
+}
+
+fn retarget(
+    term: &Terminator,
+    targets: &HashMap<Name, Name>,
+    scopes: &LoopScopes,
+    iterations: &Iterations,
+) -> TranslationResult<Terminator> {
+    let retargeted = match term {
+        Terminator::Ret(ret) => {
+            let mut ret = ret.clone();
+            ret.return_operand = match &ret.return_operand {
+                Some(o) => Some(scopes.rename(o, iterations)?),
+                None => None,
+            };
+            ret.into()
+        }
+        Terminator::Br(br) => {
+            let mut br = br.clone();
+            br.dest = targets[&br.dest].clone();
+            br.into()
+        }
+        Terminator::CondBr(cond_br) => {
+            let mut cond_br = cond_br.clone();
+            cond_br.condition = scopes.rename(&cond_br.condition, iterations)?;
+            cond_br.true_dest = targets[&cond_br.true_dest].clone();
+            cond_br.false_dest = targets[&cond_br.false_dest].clone();
+            cond_br.into()
+        }
+        Terminator::Switch(switch) => {
+            let mut switch = switch.clone();
+            switch.operand = scopes.rename(&switch.operand, iterations)?;
+            for (_, dest) in switch.dests.iter_mut() {
+                *dest = targets[dest].clone();
+            }
+            switch.default_dest = targets[&switch.default_dest].clone();
+            switch.into()
+        }
+        Terminator::Unreachable(_) => term.clone(),
+        t => unsupported!("Terminator {t}"),
+    };
+    Ok(retargeted)
+}
+
+fn natural_loops(
+    successors: &HashMap<Name, Vec<Name>>,
+    dominators: &HashMap<Name, HashSet<Name>>,
+) -> Vec<NaturalLoop> {
+    let mut predecessors: HashMap<&Name, Vec<&Name>> = HashMap::new();
+    let mut latches: HashMap<&Name, Vec<Name>> = HashMap::new();
+    for (from, tos) in successors
+        .iter()
+        .filter(|(n, _)| dominators.contains_key(*n))
+    {
+        for to in tos {
+            predecessors.entry(to).or_default().push(from);
+            // An edge to a block dominating its source is a back edge.
+            if dominators[from].contains(to) {
+                latches.entry(to).or_default().push(from.clone());
+            }
+        }
+    }
+    let mut loops: Vec<NaturalLoop> = latches
+        .into_iter()
+        .map(|(header, latches)| {
+            let mut body = HashSet::from([header.clone()]);
+            let mut stack: Vec<&Name> = latches.iter().collect();
+            while let Some(block) = stack.pop() {
+                if body.insert(block.clone()) {
+                    stack.extend(predecessors.get(block).into_iter().flatten());
+                }
+            }
+            NaturalLoop {
+                header: header.clone(),
+                body,
+                latches,
+            }
+        })
+        .collect();
+    // An enclosing loop is strictly bigger than the loops it contains.
+    loops.sort_by_key(|l| std::cmp::Reverse(l.body.len()));
+    loops
+}
+
+/// Number of times the header of the loop executes, when an exit executed on every iteration
+/// compares a constant induction variable against a constant.
+fn infer_trip_count(
+    l: &NaturalLoop,
+    blocks: &[BasicBlock],
+    dominators: &HashMap<Name, HashSet<Name>>,
+) -> Option<usize> {
+    let definitions: HashMap<&Name, &Instruction> = blocks
+        .iter()
+        .filter(|b| l.body.contains(&b.name))
+        .flat_map(|b| b.instrs.iter())
+        .filter_map(|i| i.try_get_result().map(|d| (d, i)))
+        .collect();
+    blocks
+        .iter()
+        .filter(|b| l.body.contains(&b.name))
+        .filter(|b| {
+            l.latches
+                .iter()
+                .all(|latch| dominators[latch].contains(&b.name))
+        })
+        .filter_map(|b| match &b.term {
+            Terminator::CondBr(cond_br) => {
+                let exits_when = match (
+                    l.body.contains(&cond_br.true_dest),
+                    l.body.contains(&cond_br.false_dest),
+                ) {
+                    (false, true) => true,
+                    (true, false) => false,
+                    _ => return None,
+                };
+                exit_trip_count(l, &definitions, &cond_br.condition, exits_when)
+            }
+            _ => None,
+        })
+        .min()
+}
+
+fn exit_trip_count(
+    l: &NaturalLoop,
+    definitions: &HashMap<&Name, &Instruction>,
+    condition: &Operand,
+    exits_when: bool,
+) -> Option<usize> {
+    let Instruction::ICmp(icmp) = definitions.get(local_name(condition)?)? else {
+        return None;
+    };
+    let (variable, limit, predicate) = match (
+        constant_value(&icmp.operand0),
+        constant_value(&icmp.operand1),
+    ) {
+        (None, Some(limit)) => (&icmp.operand0, limit, icmp.predicate),
+        (Some(limit), None) => (&icmp.operand1, limit, swapped(icmp.predicate)),
+        _ => return None,
+    };
+    let bits = variable.integer_bits().filter(|b| *b <= 64)?;
+    let (start, step, stepped) = induction_variable(l, definitions, local_name(variable)?)?;
+    let mask = if bits == 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    };
+    let mut value = start & mask;
+    for trip in 0..MAX_INFERRED_TRIP_COUNT {
+        let next = value.wrapping_add(step) & mask;
+        let tested = if stepped { next } else { value };
+        if compare(predicate, tested, limit & mask, bits) == exits_when {
+            return Some(trip + 1);
+        }
+        value = next;
+    }
+    None
+}
+
+/// Start and step of an induction variable `phi [start, preheader], [phi + step, latch]`,
+/// and whether `name` is the stepped value rather than the phi itself.
+fn induction_variable(
+    l: &NaturalLoop,
+    definitions: &HashMap<&Name, &Instruction>,
+    name: &Name,
+) -> Option<(u64, u64, bool)> {
+    let stepped = |value: &Name| -> Option<(&Name, u64)> {
+        let Instruction::Add(add) = definitions.get(value)? else {
+            return None;
+        };
+        match (constant_value(&add.operand0), constant_value(&add.operand1)) {
+            (None, Some(step)) => Some((local_name(&add.operand0)?, step)),
+            (Some(step), None) => Some((local_name(&add.operand1)?, step)),
+            _ => None,
+        }
+    };
+    let (phi_name, is_stepped) = match definitions.get(name)? {
+        Instruction::Phi(_) => (name, false),
+        Instruction::Add(_) => (stepped(name)?.0, true),
+        _ => return None,
+    };
+    let Instruction::Phi(phi) = definitions.get(phi_name)? else {
+        return None;
+    };
+    let mut start = None;
+    let mut step = None;
+    for (value, block) in &phi.incoming_values {
+        if l.body.contains(block) {
+            let (base, s) = stepped(local_name(value)?)?;
+            if base != phi_name || step.is_some_and(|o| o != s) {
+                return None;
+            }
+            step = Some(s);
+        } else {
+            let s = constant_value(value)?;
+            if start.is_some_and(|o| o != s) {
+                return None;
+            }
+            start = Some(s);
+        }
+    }
+    Some((start?, step?, is_stepped))
+}
+
+fn local_name(operand: &Operand) -> Option<&Name> {
+    match operand {
+        Operand::LocalOperand { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+fn constant_value(operand: &Operand) -> Option<u64> {
+    match operand {
+        Operand::ConstantOperand(c) => match c.as_ref() {
+            // llvm-ir truncates wider constants to their low 64 bits.
+            Constant::Int { bits, value } if *bits <= 64 => Some(*value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn swapped(predicate: IntPredicate) -> IntPredicate {
+    match predicate {
+        IntPredicate::EQ => IntPredicate::EQ,
+        IntPredicate::NE => IntPredicate::NE,
+        IntPredicate::UGT => IntPredicate::ULT,
+        IntPredicate::UGE => IntPredicate::ULE,
+        IntPredicate::ULT => IntPredicate::UGT,
+        IntPredicate::ULE => IntPredicate::UGE,
+        IntPredicate::SGT => IntPredicate::SLT,
+        IntPredicate::SGE => IntPredicate::SLE,
+        IntPredicate::SLT => IntPredicate::SGT,
+        IntPredicate::SLE => IntPredicate::SGE,
+    }
+}
+
+fn compare(predicate: IntPredicate, a: u64, b: u64, bits: u32) -> bool {
+    let signed = |v: u64| ((v << (64 - bits)) as i64) >> (64 - bits);
+    match predicate {
+        IntPredicate::EQ => a == b,
+        IntPredicate::NE => a != b,
+        IntPredicate::UGT => a > b,
+        IntPredicate::UGE => a >= b,
+        IntPredicate::ULT => a < b,
+        IntPredicate::ULE => a <= b,
+        IntPredicate::SGT => signed(a) > signed(b),
+        IntPredicate::SGE => signed(a) >= signed(b),
+        IntPredicate::SLT => signed(a) < signed(b),
+        IntPredicate::SLE => signed(a) <= signed(b),
+    }
+}
@@ -0,0 +1,1103 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use llvm_ir::{
+    BasicBlock, Constant, Function, Instruction, Name, Operand, Terminator, Type,
+    function::{Parameter, ParameterAttribute},
+    instruction::{Alloca, Call, GetElementPtr, Load, Store},
+    types::NamedStructDef,
+};
+use num_bigint::BigUint;
+
+use crate::{
+    IrModule,
+    instructions::*,
+    ir_circom::{
+        Structure,
+        diagnostics::{TranslationResult, unsupported},
+        instruction_handler::{
+            aggregate_field, enabled_operand, handle_alloca, handle_store, range_constraint,
+        },
+        wide::{LIMB_BITS, constant_limb, is_wide, limb_bits, limb_count, limb_name},
+    },
+    ir_module::ConstantScope,
+};
+
+/// Memory is modelled per allocation: every scalar slot, at a byte offset of the allocation,
+/// is a family of versioned signals (`{base}` for the slot at offset 0, `{base}_o{offset}`
+/// for the others). Pointers are resolved at compile time to the allocation they point into,
+/// a constant byte offset and at most one dynamic index, scaled by its stride. Constant
+/// globals are allocations too, whose slots are the constants of their initializer.
+#[derive(Debug, Default)]
+pub struct Memory {
+    /// Size in bytes of every allocation, by the signal name of its base pointer.
+    allocations: HashMap<String, u64>,
+    /// Bit width of the slots accessed so far, by allocation and byte offset.
+    slots: HashMap<String, BTreeMap<u64, u32>>,
+    pointers: HashMap<String, Pointer>,
+    /// Allocations passed as pointer parameters, with the bit width of their elements once
+    /// they are accessed.
+    inputs: HashMap<String, Option<u32>>,
+    /// Number of stores through dynamic pointers so far.
+    dynamic_stores: usize,
+    /// Number of `llvm.memset` calls with a dynamic value so far.
+    dynamic_memsets: usize,
+    /// Bytes of the constant globals accessed so far, `None` for the bytes of non-integer
+    /// constants (such as pointers).
+    constants: HashMap<String, Vec<Option<u8>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pointer {
+    base: String,
+    offset: i64,
+    /// Index signal and its stride in bytes, for pointers computed with a dynamic index.
+    index: Option<(CircomOperand, u64)>,
+}
+
+impl Memory {
+    pub fn allocate(&mut self, base: String, size: u64) {
+        self.allocations.insert(base.clone(), size);
+        self.pointers.insert(
+            base.clone(),
+            Pointer {
+                base,
+                offset: 0,
+                index: None,
+            },
+        );
+    }
+
+    /// Registers the memory behind a pointer parameter, an array of inputs of the element
+    /// type it is accessed with.
+    pub fn allocate_input(&mut self, base: String, size: u64) {
+        self.inputs.insert(base.clone(), None);
+        self.allocate(base, size);
+    }
+
+    /// Registers a constant global, whose slots are read from its initializer.
+    pub fn allocate_constant(&mut self, base: String, bytes: Vec<Option<u8>>) {
+        self.allocate(base.clone(), bytes.len() as u64);
+        self.constants.insert(base, bytes);
+    }
+
+    /// Input arrays accessed so far, with the bit width and the number of their elements.
+    pub fn input_arrays(&self) -> Vec<(String, u32, usize)> {
+        let mut arrays: Vec<(String, u32, usize)> = self
+            .inputs
+            .iter()
+            .filter_map(|(base, bits)| {
+                let bits = (*bits)?;
+                let len = self.allocations[base] / store_size(bits);
+                Some((base.clone(), bits, len as usize))
+            })
+            .collect();
+        arrays.sort();
+        arrays
+    }
+
+    fn pointer(&self, name: &str) -> TranslationResult<&Pointer> {
+        match self.pointers.get(name) {
+            Some(pointer) => Ok(pointer),
+            None => unsupported!("Pointer {name} does not point into a known allocation"),
+        }
+    }
+
+    /// Slot offsets an access of `bytes` bytes through `pointer` may touch, one per value of
+    /// its index (a single one for constant pointers).
+    fn candidates(&self, pointer: &Pointer, bytes: u64) -> TranslationResult<Vec<u64>> {
+        let size = self.allocations[&pointer.base];
+        let stride = match &pointer.index {
+            None => 0,
+            Some((_, stride)) => *stride,
+        };
+        let mut offsets = vec![];
+        let mut offset = pointer.offset;
+        while offset >= 0 && offset as u64 + bytes <= size {
+            offsets.push(offset as u64);
+            if stride == 0 {
+                break;
+            }
+            offset += stride as i64;
+        }
+        if offsets.is_empty() {
+            unsupported!(
+                "Access of {bytes} bytes at offset {} out of the {size} bytes of {}",
+                pointer.offset,
+                pointer.base
+            );
+        }
+        Ok(offsets)
+    }
+
+    /// Slots in the `length` bytes at `pointer`, with their offset from it: the elements of an
+    /// input array, or else the slots accessed so far. The other bytes of a stack allocation
+    /// have never been written, so they are still zero.
+    fn known_slots(&self, pointer: &Pointer, length: u64) -> TranslationResult<Vec<(u64, u32)>> {
+        let start = pointer.offset as u64;
+        if let Some(element) = self.inputs.get(&pointer.base) {
+            let Some(bits) = element else {
+                unsupported!("Copying the input {} before it is accessed", pointer.base);
+            };
+            let size = store_size(*bits);
+            let first = start.next_multiple_of(size);
+            return Ok((first..start + length)
+                .step_by(size as usize)
+                .filter(|offset| offset + size <= start + length)
+                .map(|offset| (offset - start, *bits))
+                .collect());
+        }
+        let Some(slots) = self.slots.get(&pointer.base) else {
+            return Ok(vec![]);
+        };
+        Ok(slots
+            .range(start..start + length)
+            .filter(|(offset, bits)| *offset + store_size(**bits) <= start + length)
+            .map(|(offset, bits)| (offset - start, *bits))
+            .collect())
+    }
+
+    /// Registers the slot of `bits` bits at `offset`, returning whether it is new. Slots of
+    /// an allocation cannot overlap, so memory has to be accessed with consistent types.
+    fn slot(&mut self, base: &str, offset: u64, bits: u32) -> TranslationResult<bool> {
+        if let Some(element) = self.inputs.get_mut(base) {
+            match element {
+                Some(element) if *element != bits => {
+                    unsupported!("Accessing the input {base} as i{bits} and as i{element}")
+                }
+                _ => *element = Some(bits),
+            }
+            if !offset.is_multiple_of(store_size(bits)) {
+                unsupported!("Unaligned access to the input {base} at offset {offset}");
+            }
+        }
+        let slots = self.slots.entry(base.to_string()).or_default();
+        if let Some(existing) = slots.get(&offset) {
+            if *existing != bits {
+                unsupported!("Accessing {base}+{offset} as i{bits} and as i{existing}");
+            }
+            return Ok(false);
+        }
+        let end = offset + store_size(bits);
+        for (other, other_bits) in slots.range(..end).rev() {
+            if other + store_size(*other_bits) > offset {
+                unsupported!("Overlapping accesses to {base}+{other} and {base}+{offset}");
+            }
+        }
+        slots.insert(offset, bits);
+        Ok(true)
+    }
+}
+
+/// The slot of `bits` bits at `offset` of a constant global.
+fn constant_slot(
+    bytes: &[Option<u8>],
+    base: &str,
+    offset: u64,
+    bits: u32,
+) -> TranslationResult<CircomOperand> {
+    let range = offset as usize..(offset + store_size(bits)) as usize;
+    let Some(bytes) = bytes[range].iter().copied().collect::<Option<Vec<u8>>>() else {
+        unsupported!("Reading a non-integer constant from {base}+{offset} as i{bits}");
+    };
+    Ok(CircomOperand::constant(
+        BigUint::from_bytes_le(&bytes),
+        bits,
+    ))
+}
+
+fn slot_name(base: &str, offset: u64) -> String {
+    match offset {
+        0 => base.to_string(),
+        _ => format!("{base}_o{offset}"),
+    }
+}
+
+fn store_size(bits: u32) -> u64 {
+    bits.div_ceil(8) as u64
+}
+
+/// Byte sizes and offsets of LLVM types, following the data layout of the module.
+pub struct Layout<'a> {
+    module: &'a IrModule,
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(module: &'a IrModule) -> Self {
+        Self { module }
+    }
+
+    fn resolve<'b>(&'b self, ty: &'b Type) -> TranslationResult<&'b Type> {
+        match ty {
+            Type::NamedStructType { name } => match self.module.types.named_struct_def(name) {
+                Some(NamedStructDef::Defined(ty)) => Ok(ty),
+                _ => unsupported!("Opaque struct {name}"),
+            },
+            other => Ok(other),
+        }
+    }
+
+    pub fn alignment(&self, ty: &Type) -> TranslationResult<u64> {
+        let alignment = match self.resolve(ty)? {
+            Type::StructType {
+                is_packed: true, ..
+            } => 1,
+            Type::StructType { element_types, .. } => {
+                let mut alignment = 1;
+                for t in element_types {
+                    alignment = alignment.max(self.alignment(t)?);
+                }
+                alignment
+            }
+            Type::ArrayType { element_type, .. } => self.alignment(element_type)?,
+            other => {
+                let alignment = self.module.data_layout.alignments.type_alignment(other);
+                (alignment.abi as u64 / 8).max(1)
+            }
+        };
+        Ok(alignment)
+    }
+
+    /// Allocation size of `ty`, including the padding up to its alignment.
+    pub fn size(&self, ty: &Type) -> TranslationResult<u64> {
+        let size = match self.resolve(ty)? {
+            Type::IntegerType { bits } => store_size(*bits),
+            Type::PointerType { .. } => {
+                self.module.data_layout.alignments.ptr_alignment(0).size as u64 / 8
+            }
+            Type::ArrayType {
+                element_type,
+                num_elements,
+            } => self.size(element_type)? * *num_elements as u64,
+            Type::StructType {
+                element_types,
+                is_packed,
+            } => match element_types.last() {
+                None => 0,
+                Some(last) => {
+                    let offsets = self.field_offsets(element_types, *is_packed)?;
+                    offsets[offsets.len() - 1] + self.size(last)?
+                }
+            },
+            other => unsupported!("Size of {other}"),
+        };
+        Ok(size.next_multiple_of(self.alignment(ty)?))
+    }
+
+    fn field_offsets(
+        &self,
+        element_types: &[llvm_ir::TypeRef],
+        packed: bool,
+    ) -> TranslationResult<Vec<u64>> {
+        let mut offset = 0u64;
+        let mut offsets = vec![];
+        for t in element_types {
+            if !packed {
+                offset = offset.next_multiple_of(self.alignment(t)?);
+            }
+            offsets.push(offset);
+            offset += self.size(t)?;
+        }
+        Ok(offsets)
+    }
+
+    /// Offset of the field `index` of a struct type, or of the element `index` of an array type,
+    /// with the type of that field.
+    fn field(&self, ty: &Type, index: u64) -> TranslationResult<(u64, llvm_ir::TypeRef)> {
+        match self.resolve(ty)? {
+            Type::StructType {
+                element_types,
+                is_packed,
+            } => {
+                let offsets = self.field_offsets(element_types, *is_packed)?;
+                Ok((
+                    offsets[index as usize],
+                    element_types[index as usize].clone(),
+                ))
+            }
+            Type::ArrayType { element_type, .. } => {
+                Ok((index * self.size(element_type)?, element_type.clone()))
+            }
+            other => unsupported!("Indexing into {other}"),
+        }
+    }
+
+    /// Writes the little-endian bytes of `constant`, found at the element indices `path` of the
+    /// initializer of `global` and placed at `offset`, into `bytes`. The bytes of pointers and
+    /// other non-integer constants are left unknown.
+    fn write_constant(
+        &self,
+        global: &Name,
+        constant: &Constant,
+        path: &mut Vec<usize>,
+        offset: u64,
+        bytes: &mut [Option<u8>],
+    ) -> TranslationResult<()> {
+        match constant {
+            Constant::Int { bits, value } => {
+                let scope = ConstantScope::Initializer { global, path };
+                let value = self.module.int_constant(&scope, *bits, *value)?;
+                let value = value.value.to_bytes_le();
+                let value = value.into_iter().chain(std::iter::repeat(0));
+                for (i, byte) in value.take(store_size(*bits) as usize).enumerate() {
+                    bytes[offset as usize + i] = Some(byte);
+                }
+            }
+            Constant::AggregateZero(ty) | Constant::Undef(ty) | Constant::Poison(ty) => {
+                let end = offset + self.size(ty)?;
+                bytes[offset as usize..end as usize].fill(Some(0));
+            }
+            Constant::Struct {
+                values, is_packed, ..
+            } => {
+                let types: Vec<_> = values.iter().map(|v| self.module.type_of(v)).collect();
+                let offsets = self.field_offsets(&types, *is_packed)?;
+                for (i, (value, field_offset)) in values.iter().zip(offsets).enumerate() {
+                    path.push(i);
+                    self.write_constant(global, value, path, offset + field_offset, bytes)?;
+                    path.pop();
+                }
+            }
+            Constant::Array {
+                element_type,
+                elements,
+            } => {
+                let stride = self.size(element_type)?;
+                for (i, element) in elements.iter().enumerate() {
+                    path.push(i);
+                    self.write_constant(global, element, path, offset + i as u64 * stride, bytes)?;
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Integer fields of `ty`, wide integers being split into their limbs.
+    pub fn scalar_fields(&self, ty: &Type) -> TranslationResult<Vec<ScalarField>> {
+        let count = match self.resolve(ty)? {
+            Type::IntegerType { bits } if is_wide(*bits) => {
+                return Ok((0..limb_count(*bits))
+                    .map(|limb| ScalarField {
+                        indices: vec![],
+                        offset: (LIMB_BITS / 8 * limb) as u64,
+                        bits: limb_bits(*bits, limb),
+                        limb: Some(limb),
+                    })
+                    .collect());
+            }
+            Type::IntegerType { bits } => {
+                return Ok(vec![ScalarField {
+                    indices: vec![],
+                    offset: 0,
+                    bits: *bits,
+                    limb: None,
+                }]);
+            }
+            Type::StructType { element_types, .. } => element_types.len(),
+            Type::ArrayType { num_elements, .. } => *num_elements,
+            other => unsupported!("Memory access of type {other}"),
+        };
+        let mut fields = vec![];
+        for i in 0..count {
+            let (offset, field_type) = self.field(ty, i as u64)?;
+            for mut field in self.scalar_fields(&field_type)? {
+                field.indices.insert(0, i as u32);
+                field.offset += offset;
+                fields.push(field);
+            }
+        }
+        Ok(fields)
+    }
+}
+
+/// An integer field of a value in memory, or one of the limbs of a wide integer field.
+pub struct ScalarField {
+    /// Aggregate indices of the field, empty for a scalar value.
+    indices: Vec<u32>,
+    /// Byte offset of the field in the value.
+    offset: u64,
+    bits: u32,
+    limb: Option<u32>,
+}
+
+impl ScalarField {
+    /// Signal holding the field of the value `value`.
+    fn name(&self, value: &str) -> String {
+        let field = match self.indices.is_empty() {
+            true => value.to_string(),
+            false => aggregate_field(value, &self.indices),
+        };
+        match self.limb {
+            None => field,
+            Some(limb) => limb_name(&field, limb),
+        }
+    }
+}
+
+/// Stack slots that are only ever stored to, such as the `.dbg.spill` slots rustc emits for the
+/// debugger. Nothing reads them back, so their stores do not affect the result.
+pub fn write_only_allocas(blocks: &[BasicBlock]) -> HashSet<Name> {
+    let instrs = blocks.iter().flat_map(|b| b.instrs.iter());
+    let mut allocas: HashSet<Name> = instrs
+        .clone()
+        .filter_map(|i| match i {
+            Instruction::Alloca(alloca) => Some(alloca.dest.clone()),
+            _ => None,
+        })
+        .collect();
+    for instr in instrs {
+        let read = match instr {
+            // Storing to a slot does not read it, storing its address does.
+            Instruction::Store(store) => vec![&store.value],
+            other => match instruction_operands(other) {
+                Some(operands) => operands,
+                None => return HashSet::new(),
+            },
+        };
+        for operand in read {
+            if let Operand::LocalOperand { name, .. } = operand {
+                allocas.remove(name);
+            }
+        }
+    }
+    for block in blocks {
+        for operand in terminator_operands(&block.term) {
+            if let Operand::LocalOperand { name, .. } = operand {
+                allocas.remove(name);
+            }
+        }
+    }
+    allocas
+}
+
+/// Operands used by `instr`, `None` for the instructions they are not known for.
+pub fn instruction_operands(instr: &Instruction) -> Option<Vec<&Operand>> {
+    let operands = match instr {
+        Instruction::Add(i) => vec![&i.operand0, &i.operand1],
+        Instruction::Sub(i) => vec![&i.operand0, &i.operand1],
+        Instruction::Mul(i) => vec![&i.operand0, &i.operand1],
+        Instruction::UDiv(i) => vec![&i.operand0, &i.operand1],
+        Instruction::SDiv(i) => vec![&i.operand0, &i.operand1],
+        Instruction::URem(i) => vec![&i.operand0, &i.operand1],
+        Instruction::SRem(i) => vec![&i.operand0, &i.operand1],
+        Instruction::And(i) => vec![&i.operand0, &i.operand1],
+        Instruction::Or(i) => vec![&i.operand0, &i.operand1],
+        Instruction::Xor(i) => vec![&i.operand0, &i.operand1],
+        Instruction::Shl(i) => vec![&i.operand0, &i.operand1],
+        Instruction::LShr(i) => vec![&i.operand0, &i.operand1],
+        Instruction::AShr(i) => vec![&i.operand0, &i.operand1],
+        Instruction::ICmp(i) => vec![&i.operand0, &i.operand1],
+        Instruction::Trunc(i) => vec![&i.operand],
+        Instruction::ZExt(i) => vec![&i.operand],
+        Instruction::SExt(i) => vec![&i.operand],
+        Instruction::PtrToInt(i) => vec![&i.operand],
+        Instruction::IntToPtr(i) => vec![&i.operand],
+        Instruction::BitCast(i) => vec![&i.operand],
+        Instruction::Freeze(i) => vec![&i.operand],
+        Instruction::ExtractValue(i) => vec![&i.aggregate],
+        Instruction::InsertValue(i) => vec![&i.aggregate, &i.element],
+        Instruction::Alloca(i) => vec![&i.num_elements],
+        Instruction::Load(i) => vec![&i.address],
+        Instruction::Store(i) => vec![&i.address, &i.value],
+        Instruction::GetElementPtr(i) => [&i.address].into_iter().chain(&i.indices).collect(),
+        Instruction::Select(i) => vec![&i.condition, &i.true_value, &i.false_value],
+        Instruction::Phi(i) => i.incoming_values.iter().map(|(v, _)| v).collect(),
+        Instruction::Call(i) => i
+            .function
+            .as_ref()
+            .right()
+            .into_iter()
+            .chain(i.arguments.iter().map(|(a, _)| a))
+            .collect(),
+        _ => return None,
+    };
+    Some(operands)
+}
+
+/// Operands used by `term`.
+pub fn terminator_operands(term: &Terminator) -> Vec<&Operand> {
+    match term {
+        Terminator::Ret(ret) => ret.return_operand.iter().collect(),
+        Terminator::CondBr(cond_br) => vec![&cond_br.condition],
+        Terminator::Switch(switch) => vec![&switch.operand],
+        _ => vec![],
+    }
+}
+
+/// Constant value of a (signed) integer index.
+fn constant_index(operand: &Operand) -> Option<i64> {
+    match operand {
+        Operand::ConstantOperand(c) => signed_constant(c),
+        _ => None,
+    }
+}
+
+fn signed_constant(constant: &Constant) -> Option<i64> {
+    match constant {
+        Constant::Int { bits, value } if *bits <= 64 => {
+            let shift = 64 - bits;
+            Some(((*value << shift) as i64) >> shift)
+        }
+        _ => None,
+    }
+}
+
+/// Pointer held by `operand`: an address computed by the function, a global or a constant
+/// `getelementptr` expression into a global.
+fn address(structure: &mut Structure, operand: &Operand) -> TranslationResult<Pointer> {
+    match operand {
+        Operand::LocalOperand { .. } => structure
+            .memory
+            .pointer(&structure.local_name(operand)?)
+            .cloned(),
+        Operand::ConstantOperand(c) => constant_address(structure, c),
+        other => unsupported!("Pointer {other}"),
+    }
+}
+
+fn constant_address(structure: &mut Structure, constant: &Constant) -> TranslationResult<Pointer> {
+    match constant {
+        Constant::GlobalReference { name, .. } => global_pointer(structure, name),
+        Constant::GetElementPtr(gep) => {
+            let indices: Option<Vec<_>> = gep.indices.iter().map(|i| signed_constant(i)).collect();
+            let (Some(indices), Some(global)) = (indices, base_global(&gep.address)) else {
+                unsupported!("Pointer {constant}");
+            };
+            let mut pointer = constant_address(structure, &gep.address)?;
+            // llvm-ir does not expose the source element type of constant expressions, the
+            // offset is the one LLVM computes.
+            let scope = structure.constant_scope()?;
+            pointer.offset += structure.module.gep_offset(&scope, global, &indices)?;
+            Ok(pointer)
+        }
+        other => unsupported!("Pointer {other}"),
+    }
+}
+
+/// Global a constant pointer is based on.
+fn base_global(constant: &Constant) -> Option<&Name> {
+    match constant {
+        Constant::GlobalReference { name, .. } => Some(name),
+        Constant::GetElementPtr(gep) => base_global(&gep.address),
+        _ => None,
+    }
+}
+
+/// Pointer to a global. Only constant globals are supported: their slots are constants read
+/// from the initializer, such as the entries of lookup tables and S-boxes.
+fn global_pointer(structure: &mut Structure, name: &Name) -> TranslationResult<Pointer> {
+    let base = global_base(name);
+    if !structure.memory.constants.contains_key(&base) {
+        let module = structure.module;
+        let Some(global) = module.get_global_var_by_name(name) else {
+            unsupported!("Pointer to the function {name}");
+        };
+        let Some(initializer) = global.initializer.as_ref().filter(|_| global.is_constant) else {
+            unsupported!("Access to the mutable or external global {name}");
+        };
+        if module.is_big_endian() {
+            unsupported!("Constant globals of big-endian targets");
+        }
+        let layout = Layout::new(module);
+        let mut bytes = vec![None; layout.size(&module.type_of(initializer))? as usize];
+        layout.write_constant(name, initializer, &mut vec![], 0, &mut bytes)?;
+        structure.memory.allocate_constant(base.clone(), bytes);
+    }
+    Ok(Pointer {
+        base,
+        offset: 0,
+        index: None,
+    })
+}
+
+/// Base name of the allocation of a global, made circom-legal.
+fn global_base(name: &Name) -> String {
+    let name = match name {
+        Name::Name(name) => name.to_string(),
+        Name::Number(number) => number.to_string(),
+    };
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("G{name}")
+}
+
+/// Value of the field `field` of a constant, found at `indices` of the constant aggregate.
+fn constant_field(
+    module: &IrModule,
+    scope: &ConstantScope,
+    constant: &Constant,
+    indices: &[u32],
+    field: &ScalarField,
+) -> TranslationResult<CircomOperand> {
+    let Some((first, rest)) = indices.split_first() else {
+        return match (constant, field.limb) {
+            (Constant::Int { bits, value }, None) => Ok(CircomOperand::Constant(
+                module.int_constant(scope, *bits, *value)?,
+            )),
+            (Constant::Int { bits, value }, Some(limb)) => Ok(constant_limb(
+                &module.int_constant(scope, *bits, *value)?,
+                limb,
+            )),
+            (Constant::AggregateZero(_) | Constant::Undef(_) | Constant::Poison(_), _) => {
+                Ok(CircomOperand::constant(0u32, field.bits))
+            }
+            (other, _) => unsupported!("Storing the constant {other}"),
+        };
+    };
+    match constant {
+        Constant::Struct { values, .. } => {
+            constant_field(module, scope, &values[*first as usize], rest, field)
+        }
+        Constant::Array { elements, .. } => {
+            constant_field(module, scope, &elements[*first as usize], rest, field)
+        }
+        Constant::AggregateZero(_) | Constant::Undef(_) | Constant::Poison(_) => {
+            Ok(CircomOperand::constant(0u32, field.bits))
+        }
+        other => unsupported!("Storing the constant {other}"),
+    }
+}
+
+/// Signal of the slot at `offset` of `base`, declaring it on first use. Slots start as zero,
+/// or as their element of the input array.
+fn slot_reference(
+    structure: &mut Structure,
+    base: &str,
+    offset: u64,
+    bits: u32,
+    instrs: &mut Vec<CircomInstr>,
+) -> TranslationResult<String> {
+    let name = slot_name(base, offset);
+    if !structure.memory.slot(base, offset, bits)? {
+        return Ok(name);
+    }
+    if !structure.memory.inputs.contains_key(base) {
+        instrs.extend(handle_alloca(structure, name.clone())?);
+        return Ok(name);
+    }
+    let element = format!("{base}[{}]", offset / store_size(bits));
+    let i = ConstraintGenerationAssigment {
+        left: structure.signals.declare_mutable_reference(name.clone())?,
+        right: Expression::Operand(CircomOperand::Reference(Reference::SignalRef(element))),
+    };
+    instrs.push(i.into());
+    Ok(name)
+}
+
+/// Size in bytes of the memory behind a pointer parameter, known from its `dereferenceable`
+/// or `byval` attribute (such as for `&[u8; 32]` or `[u32; 8]` parameters).
+fn pointer_parameter_size(
+    structure: &Structure,
+    parameter: &Parameter,
+) -> TranslationResult<Option<u64>> {
+    for attribute in &parameter.attributes {
+        match attribute {
+            ParameterAttribute::Dereferenceable(size) => return Ok(Some(*size)),
+            ParameterAttribute::ByVal(ty) => {
+                return Layout::new(structure.module).size(ty).map(Some);
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// A pointer parameter of a known size points to an input array, `signal input name[N]`,
+/// whose elements have the type of the accesses through it. Its signals are declared by
+/// [`handle_input_arrays`] once the function has been translated.
+pub fn handle_pointer_parameter(
+    structure: &mut Structure,
+    parameter: &Parameter,
+) -> TranslationResult<Vec<CircomInstr>> {
+    if let Some(size) = pointer_parameter_size(structure, parameter)? {
+        let name = parameter.name.to_simple_string();
+        structure.memory.allocate_input(name, size);
+    }
+    Ok(vec![])
+}
+
+/// Declares the input arrays accessed by the function, each element constrained to its type.
+pub fn handle_input_arrays(structure: &mut Structure) -> TranslationResult<Vec<CircomInstr>> {
+    let mut instrs = vec![];
+    for (name, bits, len) in structure.memory.input_arrays() {
+        structure.signals.declare_input_array(name.clone(), len);
+        for i in 0..len {
+            let element = Reference::SignalRef(format!("{name}[{i}]"));
+            instrs.extend(range_constraint(format!("{name}_RANGE{i}"), element, bits));
+        }
+    }
+    Ok(instrs)
+}
+
+/// Whether `value` is used by an instruction of `function`.
+pub fn uses_value(function: &Function, value: &Name) -> bool {
+    function.basic_blocks.iter().any(|block| {
+        let operands = block.instrs.iter().map(instruction_operands);
+        let terminator = Some(terminator_operands(&block.term));
+        operands.chain([terminator]).any(|operands| match operands {
+            Some(operands) => operands
+                .iter()
+                .any(|o| matches!(o, Operand::LocalOperand { name, .. } if name == value)),
+            None => true,
+        })
+    })
+}
+
+pub fn handle_alloca_instruction(
+    structure: &mut Structure,
+    alloca: &Alloca,
+) -> TranslationResult<Vec<CircomInstr>> {
+    // Such as the `.dbg.spill` slots of the debug info.
+    if structure.write_only.contains(&alloca.dest) {
+        return Ok(vec![]);
+    }
+    let Some(count) = constant_index(&alloca.num_elements) else {
+        unsupported!("Dynamically sized alloca: {alloca}");
+    };
+    let size = Layout::new(structure.module).size(&alloca.allocated_type)? * count as u64;
+    let base = structure.value_name(&alloca.dest)?;
+    structure.memory.allocate(base, size);
+    Ok(vec![])
+}
+
+/// Resolves the address computed by a `getelementptr`. The first index steps over whole
+/// source elements, the following ones into the fields of the aggregate type. At most one
+/// index can be dynamic, it is kept as a signal with its stride.
+pub fn handle_gep_instruction(
+    structure: &mut Structure,
+    gep: &GetElementPtr,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let layout = Layout::new(structure.module);
+    let mut pointer = address(structure, &gep.address)?;
+    let mut ty = gep.source_element_type.clone();
+    for (i, index) in gep.indices.iter().enumerate() {
+        if i > 0 && matches!(layout.resolve(&ty)?, Type::StructType { .. }) {
+            let Some(index) = constant_index(index) else {
+                unsupported!("Dynamic struct field index: {gep}");
+            };
+            let (offset, field) = layout.field(&ty, index as u64)?;
+            pointer.offset += offset as i64;
+            ty = field;
+            continue;
+        }
+        if i > 0 {
+            ty = layout.field(&ty, 0)?.1;
+        }
+        let stride = layout.size(&ty)?;
+        match constant_index(index) {
+            Some(index) => pointer.offset += index * stride as i64,
+            None if pointer.index.is_some() => {
+                unsupported!("More than one dynamic index into {}: {gep}", pointer.base)
+            }
+            None => pointer.index = Some((structure.operand(index)?, stride)),
+        }
+    }
+    let dest = structure.value_name(&gep.dest)?;
+    structure.memory.pointers.insert(dest, pointer);
+    Ok(vec![])
+}
+
+pub fn handle_load_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    load: &Load,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let pointer = address(structure, &load.address)?;
+    let dest = structure.value_name(&load.dest)?;
+    let fields = Layout::new(structure.module).scalar_fields(&load.loaded_ty)?;
+    let mut instrs = vec![];
+    for scalar in fields {
+        let (field, bits) = (scalar.name(&dest), scalar.bits);
+        let field_pointer = Pointer {
+            offset: pointer.offset + scalar.offset as i64,
+            ..pointer.clone()
+        };
+        let candidates = structure
+            .memory
+            .candidates(&field_pointer, store_size(bits))?;
+        let mut values = vec![];
+        for offset in candidates {
+            if let Some(bytes) = structure.memory.constants.get(&pointer.base) {
+                values.push(constant_slot(bytes, &pointer.base, offset, bits)?);
+                continue;
+            }
+            let slot = slot_reference(structure, &pointer.base, offset, bits, &mut instrs)?;
+            values.push(structure.signals.read_mutable_reference(slot)?);
+        }
+        let value = match &pointer.index {
+            None => values.remove(0),
+            Some((index, _)) => {
+                let component = ComponentInstatiation {
+                    name: format!("{field}_SEL"),
+                    component: "IndexSelector".to_string(),
+                    args: vec![values.len() as u32],
+                };
+                let index = ConstraintGenerationAssigment {
+                    left: component.field("index"),
+                    right: Expression::Operand(index.clone()),
+                };
+                let enabled = ConstraintGenerationAssigment {
+                    left: component.field("enabled"),
+                    right: Expression::Operand(enabled_operand(condition)),
+                };
+                let inputs = values.iter().enumerate().map(|(k, value)| {
+                    ConstraintGenerationAssigment {
+                        left: component.field(&format!("in[{k}]")),
+                        right: Expression::Operand(value.clone()),
+                    }
+                    .into()
+                });
+                instrs.push(component.clone().into());
+                instrs.extend(inputs);
+                instrs.push(index.into());
+                instrs.push(enabled.into());
+                CircomOperand::Reference(component.field("out"))
+            }
+        };
+        let i = ConstraintGenerationAssigment {
+            left: structure.signals.get_reference(field),
+            right: Expression::Operand(value),
+        };
+        instrs.push(i.into());
+    }
+    Ok(instrs)
+}
+
+/// Stores a value slot by slot. A store through a dynamic pointer decodes the index into one
+/// flag per candidate slot, each slot being written under the block condition and its flag.
+pub fn handle_store_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    store: &Store,
+) -> TranslationResult<Vec<CircomInstr>> {
+    if let Operand::LocalOperand { name, .. } = &store.address
+        && structure.write_only.contains(name)
+    {
+        return Ok(vec![]);
+    }
+    let pointer = address(structure, &store.address)?;
+    if structure.memory.constants.contains_key(&pointer.base) {
+        unsupported!("Store to the constant global {}", pointer.base);
+    }
+    let layout = Layout::new(structure.module);
+    let ty = structure.module.type_of(&store.value);
+    let fields = layout.scalar_fields(&ty)?;
+    let mut instrs = vec![];
+
+    let mut slot_conditions = None;
+    if let Some((index, _)) = &pointer.index {
+        let count = structure
+            .memory
+            .candidates(&pointer, layout.size(&ty)?.max(1))?
+            .len();
+        let name = format!("STORE_{}", structure.memory.dynamic_stores);
+        structure.memory.dynamic_stores += 1;
+        let component = ComponentInstatiation {
+            name: format!("{name}_DEC"),
+            component: "IndexDecoder".to_string(),
+            args: vec![count as u32],
+        };
+        let index = ConstraintGenerationAssigment {
+            left: component.field("index"),
+            right: Expression::Operand(index.clone()),
+        };
+        let enabled = ConstraintGenerationAssigment {
+            left: component.field("enabled"),
+            right: Expression::Operand(enabled_operand(condition)),
+        };
+        instrs.extend([component.clone().into(), index.into(), enabled.into()]);
+        let mut conditions = vec![];
+        for k in 0..count {
+            let flag = CircomOperand::Reference(component.field(&format!("out[{k}]")));
+            let slot_condition = match condition {
+                None => flag,
+                Some(condition) => {
+                    let signal = structure.signals.get_reference(format!("{name}_EN{k}"));
+                    let i = ConstraintGenerationAssigment {
+                        left: signal.clone(),
+                        right: Expression::BinaryOperation(BinaryOperation {
+                            left: condition.clone(),
+                            op: BinaryOperationType::Mul,
+                            right: flag,
+                        }),
+                    };
+                    instrs.push(i.into());
+                    CircomOperand::Reference(signal)
+                }
+            };
+            conditions.push(slot_condition);
+        }
+        slot_conditions = Some(conditions);
+    }
+
+    for scalar in fields {
+        let bits = scalar.bits;
+        let value = match &store.value {
+            value @ Operand::LocalOperand { .. }
+                if scalar.indices.is_empty() && scalar.limb.is_none() =>
+            {
+                structure.operand(value)?
+            }
+            value @ Operand::LocalOperand { .. } => CircomOperand::Reference(Reference::SignalRef(
+                scalar.name(&structure.local_name(value)?),
+            )),
+            Operand::ConstantOperand(c) => {
+                let scope = structure.constant_scope()?;
+                constant_field(structure.module, &scope, c, &scalar.indices, &scalar)?
+            }
+            other => unsupported!("Storing {other}"),
+        };
+        let field_pointer = Pointer {
+            offset: pointer.offset + scalar.offset as i64,
+            ..pointer.clone()
+        };
+        let candidates = structure
+            .memory
+            .candidates(&field_pointer, store_size(bits))?;
+        for (k, offset) in candidates.into_iter().enumerate() {
+            let slot = slot_reference(structure, &pointer.base, offset, bits, &mut instrs)?;
+            let condition = match &slot_conditions {
+                None => condition.clone(),
+                Some(conditions) => match conditions.get(k) {
+                    Some(c) => Some(c.clone()),
+                    None => continue,
+                },
+            };
+            instrs.extend(handle_store(structure, &condition, slot, value.clone())?);
+        }
+    }
+    Ok(instrs)
+}
+
+/// Constant length of a memory intrinsic, with its destination pointer. `None` when the
+/// destination is a write-only stack slot, whose stores are dropped.
+fn memory_intrinsic_target(
+    structure: &mut Structure,
+    call: &Call,
+) -> TranslationResult<Option<(Pointer, u64)>> {
+    let [(dest, _), _, (length, _), ..] = &call.arguments[..] else {
+        unsupported!("{call}");
+    };
+    if let Operand::LocalOperand { name, .. } = dest
+        && structure.write_only.contains(name)
+    {
+        return Ok(None);
+    }
+    let Some(length) = constant_index(length) else {
+        unsupported!("Memory intrinsic with a dynamic length: {call}");
+    };
+    let pointer = address(structure, dest)?;
+    if pointer.index.is_some() {
+        unsupported!("Memory intrinsic through a dynamic pointer: {call}");
+    }
+    if structure.memory.constants.contains_key(&pointer.base) {
+        unsupported!("Store to the constant global {}", pointer.base);
+    }
+    Ok(Some((pointer, length as u64)))
+}
+
+/// `llvm.memcpy` and `llvm.memmove` copy the slots of the source range slot by slot, every
+/// copy being a store under the block condition. The copied slots are the ones either range
+/// has been accessed with so far, so both have to be accessed with the same types. Every
+/// slot is read before the first one is written, overlapping ranges are copied correctly.
+pub fn handle_memcpy(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some((dest, length)) = memory_intrinsic_target(structure, call)? else {
+        return Ok(vec![]);
+    };
+    let source = address(structure, &call.arguments[1].0)?;
+    if source.index.is_some() {
+        unsupported!("Memory intrinsic through a dynamic pointer: {call}");
+    }
+    let mut slots = structure.memory.known_slots(&source, length)?;
+    slots.extend(structure.memory.known_slots(&dest, length)?);
+    slots.sort();
+    slots.dedup();
+    if slots.is_empty() && structure.memory.constants.contains_key(&source.base) {
+        unsupported!(
+            "Copying the constant {} before its type is known",
+            source.base
+        );
+    }
+
+    let mut instrs = vec![];
+    let mut values = vec![];
+    for (offset, bits) in &slots {
+        let offset = source.offset as u64 + offset;
+        let value = match structure.memory.constants.get(&source.base) {
+            Some(bytes) => constant_slot(bytes, &source.base, offset, *bits)?,
+            None => {
+                let slot = slot_reference(structure, &source.base, offset, *bits, &mut instrs)?;
+                structure.signals.read_mutable_reference(slot)?
+            }
+        };
+        values.push(value);
+    }
+    for ((offset, bits), value) in slots.into_iter().zip(values) {
+        let offset = dest.offset as u64 + offset;
+        let slot = slot_reference(structure, &dest.base, offset, bits, &mut instrs)?;
+        instrs.extend(handle_store(structure, condition, slot, value)?);
+    }
+    Ok(instrs)
+}
+
+/// `llvm.memset` stores the byte value in every slot of the range accessed so far, under the
+/// block condition. Filling with zero bytes leaves the slots that have not been accessed yet
+/// untouched, as they are still zero.
+pub fn handle_memset(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    call: &Call,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some((dest, length)) = memory_intrinsic_target(structure, call)? else {
+        return Ok(vec![]);
+    };
+    let byte = structure.operand(&call.arguments[1].0)?;
+    let slots = structure.memory.known_slots(&dest, length)?;
+    let covered: u64 = slots.iter().map(|(_, bits)| store_size(*bits)).sum();
+    if covered < length && !byte.is_constant(0) {
+        unsupported!("Filling memory that has not been accessed yet with non-zero bytes: {call}");
+    }
+
+    let mut instrs = vec![];
+    let mut widened: HashMap<u32, CircomOperand> = HashMap::new();
+    let memset = structure.memory.dynamic_memsets;
+    for (offset, bits) in slots {
+        // The byte repeated over the slot: `byte * 0x0101...01`.
+        let factor = (0..store_size(bits)).fold(BigUint::from(0u32), |f, _| (f << 8) + 1u32);
+        let value = match &byte {
+            CircomOperand::Constant(c) => CircomOperand::constant(&c.value * &factor, bits),
+            dynamic => widened
+                .entry(bits)
+                .or_insert_with(|| {
+                    let signal = structure
+                        .signals
+                        .get_reference(format!("MEMSET_{memset}_I{bits}"));
+                    let i = ConstraintGenerationAssigment {
+                        left: signal.clone(),
+                        right: Expression::BinaryOperation(BinaryOperation {
+                            left: dynamic.clone(),
+                            op: BinaryOperationType::Mul,
+                            right: CircomOperand::constant(factor, bits),
+                        }),
+                    };
+                    instrs.push(i.into());
+                    CircomOperand::Reference(signal)
+                })
+                .clone(),
+        };
+        let offset = dest.offset as u64 + offset;
+        let slot = slot_reference(structure, &dest.base, offset, bits, &mut instrs)?;
+        instrs.extend(handle_store(structure, condition, slot, value)?);
+    }
+    if let CircomOperand::Reference(_) = byte {
+        structure.memory.dynamic_memsets += 1;
+    }
+    Ok(instrs)
+}
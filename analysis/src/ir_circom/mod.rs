@@ -1,18 +1,36 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use llvm_ir::{BasicBlock, Function, Name};
+use llvm_ir::{Constant, Function, Name, Operand, Terminator};
 
+mod call_policy;
 mod control_flow;
+pub(crate) mod diagnostics;
 mod instruction_handler;
+mod intrinsics;
+mod loops;
+mod memory;
 mod signal_declarations;
+pub(crate) mod wide;
+
+pub use call_policy::{CallAction, CallPolicy, TranslationReport};
+pub use diagnostics::{Diagnostics, TranslationError};
 
 use crate::{
+    IrModule,
     circom_codegen::CircomCodeGenerator,
+    demangle,
     instructions::*,
     ir_circom::{
-        control_flow::{Branch, BranchNode, SimpleBranch, compute_cfg},
-        instruction_handler::{handle_alloca, handle_return_terminator},
+        control_flow::{ParentInfo, compute_cfg},
+        diagnostics::{TranslationResult, unsupported},
+        instruction_handler::{handle_alloca, handle_parameter, handle_return_terminator},
+        loops::{BlockInstance, Iterations, LoopScopes, unroll_loops},
+        memory::{
+            Memory, handle_input_arrays, instruction_operands, terminator_operands,
+            write_only_allocas,
+        },
     },
+    ir_module::ConstantScope,
 };
 use instruction_handler::handle_instruction;
 use signal_declarations::SignalDeclarations;
@@ -24,11 +42,11 @@ struct InstructionConsumer {
 }
 
 impl InstructionConsumer {
-    pub fn push(&mut self, instr: CircomInstr) {
+    pub fn push(&mut self, instr: CircomInstr) -> TranslationResult<()> {
         match instr {
             CircomInstr::ConstraintGenerationAssigment(cgs) => match self.constrs.get(&cgs.left) {
-                Some(o) if &cgs.right == o => return,
-                Some(_) => panic!("Assigning different value to constraint!"),
+                Some(o) if &cgs.right == o => {}
+                Some(_) => unsupported!("Assigning different values to {}", cgs.left.to_circom()),
                 None => {
                     self.constrs.insert(cgs.left.clone(), cgs.right.clone());
                     self.instrs.push(cgs.into())
@@ -36,38 +54,252 @@ impl InstructionConsumer {
             },
             other => self.instrs.push(other),
         }
+        Ok(())
     }
-    pub fn extend(&mut self, instrs: Vec<CircomInstr>) {
+    pub fn extend(&mut self, instrs: Vec<CircomInstr>) -> TranslationResult<()> {
         for i in instrs {
-            self.push(i)
+            self.push(i)?;
         }
+        Ok(())
     }
     pub fn instructions(self) -> Vec<CircomInstr> {
         self.instrs
     }
 }
 
-pub fn ir_to_circom(name: String, function: &Function) -> Template {
-    let signals = SignalDeclarations::new(&function.parameters);
-    let cfg = compute_cfg(&function.basic_blocks);
-    let mut structure = Structure::new(signals, cfg.branch_conditions);
+/// Settings of the translation that cannot be recovered from the IR itself.
+#[derive(Debug, Clone, Default)]
+pub struct TranslationOptions {
+    /// Unrolling bound of the loops whose trip count cannot be inferred, by function pattern,
+    /// in the order they were given.
+    pub loop_bounds: Vec<(String, usize)>,
+    /// Unrolling bound used for the functions missing from `loop_bounds`.
+    pub default_loop_bound: Option<usize>,
+    /// How the calls of each callee are translated.
+    pub call_policy: CallPolicy,
+}
+
+impl TranslationOptions {
+    /// Bound of the loops of `function`. As with [`crate::functions_matching`], a pattern
+    /// equal to its symbol or demangled path is preferred to the ones they only contain,
+    /// the first one given winning. Several distinct patterns contained in them are ambiguous.
+    pub fn loop_bound(&self, function: &str) -> TranslationResult<Option<usize>> {
+        let path = demangle(function);
+        let exact = self
+            .loop_bounds
+            .iter()
+            .find(|(pattern, _)| function == pattern || path == *pattern);
+        if let Some((_, bound)) = exact {
+            return Ok(Some(*bound));
+        }
+        let mut matching = self
+            .loop_bounds
+            .iter()
+            .filter(|(pattern, _)| function.contains(pattern) || path.contains(pattern));
+        let Some((first, bound)) = matching.next() else {
+            return Ok(self.default_loop_bound);
+        };
+        let others: Vec<_> = matching
+            .filter(|(pattern, _)| pattern != first)
+            .map(|(pattern, _)| pattern.as_str())
+            .collect();
+        if !others.is_empty() {
+            unsupported!(
+                "Ambiguous loop bound of {path}: matched by {first}, {}",
+                others.join(", ")
+            );
+        }
+        Ok(Some(*bound))
+    }
+}
+
+/// Translates `function` into the template `name` and, following the call graph, every
+/// function it calls into a template of its own, shared by all of its call sites.
+/// The report lists the calls left out of the circuit by the call policy. Every construct
+/// that cannot be translated is reported in the returned [`Diagnostics`].
+pub fn ir_to_circom(
+    name: String,
+    function: &Function,
+    module: &IrModule,
+    options: &TranslationOptions,
+) -> Result<(Vec<Template>, TranslationReport), Diagnostics> {
+    let translated_function = function_to_circom(name, function, module, options, false, &[]);
+    let mut templates = vec![translated_function.template];
+    let mut report = translated_function.report;
+    let mut diagnostics = translated_function.diagnostics;
+    let mut translated = HashSet::new();
+    translate_callees(
+        translated_function.callees,
+        module,
+        options,
+        &mut vec![function.name.clone()],
+        &mut translated,
+        &mut templates,
+        &mut report,
+        &mut diagnostics,
+    );
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+    Ok((templates, report))
+}
+
+/// Name of the template a function of the module is translated to when called.
+pub fn template_name(function: &str) -> String {
+    let name: String = function
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("F{name}")
+}
+
+#[allow(clippy::too_many_arguments)]
+fn translate_callees(
+    callees: Vec<String>,
+    module: &IrModule,
+    options: &TranslationOptions,
+    stack: &mut Vec<String>,
+    translated: &mut HashSet<String>,
+    templates: &mut Vec<Template>,
+    report: &mut TranslationReport,
+    diagnostics: &mut Diagnostics,
+) {
+    for callee in callees {
+        let caller = stack.last().cloned().unwrap_or_default();
+        let error = |message| TranslationError {
+            function: demangle(&caller),
+            block: None,
+            instruction: None,
+            message,
+        };
+        // Circom templates cannot instantiate themselves.
+        if stack.contains(&callee) {
+            diagnostics.push(error(format!("Recursive call to {}", demangle(&callee))));
+            continue;
+        }
+        if !translated.insert(callee.clone()) {
+            continue;
+        }
+        let Some(function) = module.get_func_by_name(&callee) else {
+            diagnostics.push(error(format!(
+                "{} is only declared, its body is not available",
+                demangle(&callee)
+            )));
+            continue;
+        };
+        let name = template_name(&callee);
+        let translated_function = function_to_circom(name, function, module, options, true, &[]);
+        templates.push(translated_function.template);
+        report.extend(translated_function.report);
+        diagnostics.extend(translated_function.diagnostics);
+        stack.push(callee);
+        translate_callees(
+            translated_function.callees,
+            module,
+            options,
+            stack,
+            translated,
+            templates,
+            report,
+            diagnostics,
+        );
+        stack.pop();
+    }
+}
+
+struct TranslatedFunction {
+    template: Template,
+    /// Functions called as templates, in the order of their first call site.
+    callees: Vec<String>,
+    report: TranslationReport,
+    diagnostics: Diagnostics,
+}
+
+/// Translates a single function. The template of a called function has an `ENABLED_` input,
+/// every constraint of the function only being enforced when its call site is executed.
+/// `inlined_into` lists the functions the translated one is being inlined into.
+fn function_to_circom(
+    name: String,
+    function: &Function,
+    module: &IrModule,
+    options: &TranslationOptions,
+    called: bool,
+    inlined_into: &[String],
+) -> TranslatedFunction {
+    let mut signals = SignalDeclarations::new(&function.parameters);
+    let root_condition = called.then(|| {
+        let enabled = signals.declare_input(signals.enabled_signal_name());
+        CircomOperand::Reference(enabled)
+    });
+    let cfg = options
+        .loop_bound(&function.name)
+        .and_then(|bound| unroll_loops(&function.basic_blocks, bound))
+        .and_then(|unrolled| Ok((unrolled.scopes, compute_cfg(&unrolled.instances)?)));
+    let (scopes, cfg) = match cfg {
+        Ok(cfg) => cfg,
+        Err(error) => {
+            let error = error.at(&demangle(&function.name), None, None);
+            return TranslatedFunction {
+                template: Template {
+                    name,
+                    instructions: vec![],
+                },
+                callees: vec![],
+                report: TranslationReport::default(),
+                diagnostics: Diagnostics::from(error),
+            };
+        }
+    };
+    let mut structure = Structure::new(
+        signals,
+        module,
+        options,
+        function,
+        inlined_into,
+        cfg.equivalent,
+        cfg.parents,
+        scopes,
+        &cfg.sorted,
+        root_condition,
+    );
     let mut circom_instructions = InstructionConsumer::default();
+    // The result is returned in `OUTPUT_`, or in its limbs when wide.
     let output_name = structure.signals.output_signal_name();
-    circom_instructions.extend(handle_alloca(&mut structure, output_name));
+    let output_bits = function.return_type.integer_bits().unwrap_or(0);
+    let outputs = wide::value_signals(&output_name, output_bits);
+    structure.translate(&mut circom_instructions, None, None, |s, instrs| {
+        for output in &outputs {
+            instrs.extend(handle_alloca(s, output.clone())?)?;
+        }
+        Ok(())
+    });
+    for parameter in &function.parameters {
+        let instruction = Some(format!("{} {}", parameter.ty, parameter.name));
+        structure.translate(&mut circom_instructions, None, instruction, |s, instrs| {
+            instrs.extend(handle_parameter(s, parameter)?)
+        });
+    }
 
-    for block in &cfg.sorted {
-        walk_block(block, &mut circom_instructions, &mut structure);
+    for instance in &cfg.sorted {
+        walk_block(instance, &mut circom_instructions, &mut structure);
     }
+    structure.translate(&mut circom_instructions, None, None, |s, instrs| {
+        instrs.extend(handle_input_arrays(s)?)
+    });
 
-    circom_instructions.push(
-        ConstraintGenerationAssigment {
-            left: structure.signals.output_signal_final_reference(),
-            right: Expression::Operand(structure.signals.read_output_signal()),
+    structure.translate(&mut circom_instructions, None, None, |s, instrs| {
+        for output in outputs {
+            let value = s.signals.read_mutable_reference(output.clone())?;
+            let assignment = ConstraintGenerationAssigment {
+                left: s.signals.declare_output(output),
+                right: Expression::Operand(value),
+            };
+            instrs.push(assignment.into())?;
         }
-        .into(),
-    );
+        Ok(())
+    });
 
-    Template {
+    let template = Template {
         name,
         instructions: vec![
             structure.signals.signals_instructions(),
@@ -76,147 +308,428 @@ pub fn ir_to_circom(name: String, function: &Function) -> Template {
         .into_iter()
         .flatten()
         .collect(),
+    };
+    let mut callees = vec![];
+    for callee in structure.callees {
+        if !callees.contains(&callee) {
+            callees.push(callee);
+        }
+    }
+    TranslatedFunction {
+        template,
+        callees,
+        report: structure.report,
+        diagnostics: structure.diagnostics,
     }
 }
 
+/// Translates the instructions of a block instance. An instruction that cannot be translated
+/// is reported, and the instructions using its result are skipped rather than reported again.
 fn walk_block(
-    block: &BasicBlock,
+    instance: &BlockInstance,
     circom_instructions: &mut InstructionConsumer,
-    mut structure: &mut Structure,
+    structure: &mut Structure,
 ) {
-    let condition = structure.declare_condition(&block.name, circom_instructions);
-    for instruction in &block.instrs {
-        circom_instructions.extend(handle_instruction(&mut structure, &condition, instruction));
+    let block = &instance.block;
+    structure.current_block = block.name.clone();
+    // A block reached from a block whose predicate could not be declared is skipped, like
+    // the instructions using a failed value: its own errors would only be consequences.
+    let condition = match structure.follows_skipped_block(&block.name) {
+        true => None,
+        false => structure.translate(circom_instructions, Some(&block.name), None, |s, instrs| {
+            s.declare_condition(&block.name, instrs)
+        }),
+    };
+    let Some(condition) = condition else {
+        let results = block.instrs.iter().filter_map(|i| i.try_get_result());
+        structure.failed.extend(results.cloned());
+        return;
+    };
+    // A block ending in `unreachable` (such as a panic) is constrained never to execute,
+    // so whatever it computes before does not matter.
+    if !matches!(block.term, Terminator::Unreachable(_)) {
+        for (index, instruction) in block.instrs.iter().enumerate() {
+            structure.current_instruction = index;
+            let operands = instruction_operands(instruction).unwrap_or_default();
+            if structure.uses_failed(&operands) {
+                structure
+                    .failed
+                    .extend(instruction.try_get_result().cloned());
+                continue;
+            }
+            let translated = structure.translate(
+                circom_instructions,
+                Some(&block.name),
+                Some(instruction.to_string()),
+                |s, instrs| instrs.extend(handle_instruction(s, &condition, instruction)?),
+            );
+            if translated.is_none() {
+                structure
+                    .failed
+                    .extend(instruction.try_get_result().cloned());
+            }
+        }
+    }
+    structure.current_instruction = block.instrs.len();
+    if !structure.uses_failed(&terminator_operands(&block.term)) {
+        let terminator = Some(block.term.to_string());
+        structure.translate(
+            circom_instructions,
+            Some(&block.name),
+            terminator,
+            |s, instrs| instrs.extend(handle_return_terminator(s, &condition, &block.term)?),
+        );
     }
-    circom_instructions.extend(handle_return_terminator(structure, &condition, &block.term));
 }
 
-pub struct Structure {
+pub struct Structure<'a> {
     signals: SignalDeclarations,
-    branch_conditions: HashMap<Name, Branch>,
-    declared_conditions: HashMap<Branch, CircomOperand>,
+    module: &'a IrModule,
+    options: &'a TranslationOptions,
+    /// The translated function, followed by the functions it is being inlined into.
+    function_stack: Vec<String>,
+    /// Stack slots that are never read, whose stores are dropped.
+    write_only: HashSet<Name>,
+    memory: Memory,
+    /// Blocks sharing the execution predicate of their immediate dominator.
+    equivalent: HashMap<Name, Name>,
+    parents: HashMap<Name, Vec<ParentInfo>>,
+    scopes: LoopScopes,
+    /// Block instances by name, with the block they copy and the loop iterations they execute.
+    instances: HashMap<Name, (Name, Iterations)>,
+    /// Index of each block of the IR in its function.
+    block_indices: HashMap<Name, usize>,
+    current_block: Name,
+    /// Index of the instruction being translated in its block, the terminator coming last.
+    current_instruction: usize,
+    /// Condition of the entry block, `None` when it is always executed.
+    root_condition: Option<CircomOperand>,
+    /// Functions called so far, in the order of their call sites.
+    callees: Vec<String>,
+    /// Number of calls inlined so far.
+    inlined: usize,
+    report: TranslationReport,
+    /// Execution predicates of the blocks walked so far, `None` for the ones always executed.
+    predicates: HashMap<Name, Option<CircomOperand>>,
+    /// Predicates of the edges between blocks, by source and destination.
+    edge_predicates: HashMap<(Name, Name), Option<CircomOperand>>,
     conditions: HashMap<String, String>,
     conditions_count: usize,
+    diagnostics: Diagnostics,
+    /// Values whose instruction could not be translated.
+    failed: HashSet<Name>,
 }
 
-impl Structure {
-    fn new(signals: SignalDeclarations, branch_conditions: HashMap<Name, Branch>) -> Self {
+impl<'a> Structure<'a> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        signals: SignalDeclarations,
+        module: &'a IrModule,
+        options: &'a TranslationOptions,
+        function: &Function,
+        inlined_into: &[String],
+        equivalent: HashMap<Name, Name>,
+        parents: HashMap<Name, Vec<ParentInfo>>,
+        scopes: LoopScopes,
+        instances: &[BlockInstance],
+        root_condition: Option<CircomOperand>,
+    ) -> Self {
         Self {
             signals,
-            branch_conditions,
-            declared_conditions: HashMap::new(),
+            module,
+            options,
+            function_stack: [function.name.clone()]
+                .into_iter()
+                .chain(inlined_into.iter().cloned())
+                .collect(),
+            write_only: write_only_allocas(&function.basic_blocks),
+            memory: Memory::default(),
+            equivalent,
+            parents,
+            scopes,
+            instances: instances
+                .iter()
+                .map(|i| {
+                    let info = (i.original.clone(), i.iterations.clone());
+                    (i.block.name.clone(), info)
+                })
+                .collect(),
+            block_indices: (function.basic_blocks.iter().enumerate())
+                .map(|(i, block)| (block.name.clone(), i))
+                .collect(),
+            current_block: Name::from(0),
+            current_instruction: 0,
+            root_condition,
+            callees: vec![],
+            inlined: 0,
+            report: TranslationReport::default(),
+            predicates: HashMap::new(),
+            edge_predicates: HashMap::new(),
             conditions: HashMap::new(),
             conditions_count: 0,
+            diagnostics: Diagnostics::default(),
+            failed: HashSet::new(),
         }
     }
-    fn declare_condition(
+    /// Runs `translate`, which adds its instructions to `circom_instructions`. An error is
+    /// recorded as concerning `instruction` in `block` (unless it names its own location), and
+    /// `None` is returned.
+    fn translate<T>(
         &mut self,
-        block: &Name,
-        instrs: &mut InstructionConsumer,
-    ) -> Option<CircomOperand> {
-        let branch = self.branch_conditions.get(block).unwrap().clone();
-        self.branch_operand(&branch, instrs)
+        circom_instructions: &mut InstructionConsumer,
+        block: Option<&Name>,
+        instruction: Option<String>,
+        translate: impl FnOnce(&mut Self, &mut InstructionConsumer) -> TranslationResult<T>,
+    ) -> Option<T> {
+        match translate(self, circom_instructions) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                // Errors name the block of the IR, not its unrolled instance.
+                let block = block.map(|block| self.instances.get(block).map_or(block, |(b, _)| b));
+                let function = demangle(&self.function_stack[0]);
+                let error = error.at(&function, block.map(|b| b.to_string()), instruction);
+                self.diagnostics.push(error);
+                None
+            }
+        }
+    }
+    /// Whether one of `operands` is the result of an instruction that could not be translated.
+    fn uses_failed(&self, operands: &[&Operand]) -> bool {
+        operands.iter().any(|operand| match operand {
+            Operand::LocalOperand { name, .. } => self.failed.contains(name),
+            _ => false,
+        })
+    }
+    /// Signal name of the value `value` as seen from the current block instance.
+    fn value_name(&self, value: &Name) -> TranslationResult<String> {
+        self.local_name_in(value, &self.current_block)
     }
 
-    fn branch_operand(
-        &mut self,
-        branch: &Branch,
-        instrs: &mut InstructionConsumer,
-    ) -> Option<CircomOperand> {
-        if let Some(condition) = self.declared_conditions.get(branch) {
-            return Some(condition.clone());
-        }
-        let operands: Vec<CircomOperand> = match branch {
-            Branch::Simple(s) => match self.simple_branch_operand(s, instrs) {
-                None => return None,
-                Some(op) => {
-                    self.declared_conditions.insert(branch.clone(), op.clone());
-                    return Some(op);
-                }
+    fn local_name_in(&self, value: &Name, instance: &Name) -> TranslationResult<String> {
+        let (_, iterations) = &self.instances[instance];
+        self.scopes.value_name(value, iterations)
+    }
+
+    /// Signal name of the local `operand` as seen from the current block instance.
+    fn local_name(&self, operand: &Operand) -> TranslationResult<String> {
+        match operand {
+            Operand::LocalOperand { name, .. } => self.value_name(name),
+            other => unsupported!("{other} used as a local value"),
+        }
+    }
+
+    /// `operand` as seen from the current block instance.
+    fn operand(&self, operand: &Operand) -> TranslationResult<CircomOperand> {
+        self.operand_in(operand, &self.current_block)
+    }
+
+    /// `operand` as seen from the block instance `instance`.
+    fn operand_in(&self, operand: &Operand, instance: &Name) -> TranslationResult<CircomOperand> {
+        match operand {
+            Operand::LocalOperand { name, .. } => Ok(CircomOperand::Reference(
+                Reference::SignalRef(self.local_name_in(name, instance)?),
+            )),
+            Operand::ConstantOperand(constant) => match constant.as_ref() {
+                Constant::Int { bits, value } => Ok(CircomOperand::Constant(
+                    self.module
+                        .int_constant(&self.constant_scope()?, *bits, *value)?,
+                )),
+                _ => CircomOperand::try_from(operand),
             },
-            Branch::Or(or) => or
-                .into_iter()
-                .map(|o| self.branch_operand(o, instrs))
-                .flatten()
-                .collect(),
+            other => CircomOperand::try_from(other),
+        }
+    }
+
+    /// Where the constants of the instruction being translated are used.
+    fn constant_scope(&self) -> TranslationResult<ConstantScope<'_>> {
+        let (block, _) = &self.instances[&self.current_block];
+        let Some(&block) = self.block_indices.get(block) else {
+            unsupported!("Constants of {block}, which is not a block of the IR");
         };
+        Ok(ConstantScope::Instruction {
+            function: &self.function_stack[0],
+            block,
+            instruction: self.current_instruction,
+        })
+    }
 
-        if operands.is_empty() {
-            return None;
+    /// Instances of the block `original` from which control may flow into the current block.
+    fn parent_instances(&self, original: &Name) -> Vec<Name> {
+        let mut instances: Vec<Name> = vec![];
+        for parent in self.parents.get(&self.current_block).into_iter().flatten() {
+            let name = parent.name();
+            if &self.instances[name].0 == original && !instances.contains(name) {
+                instances.push(name.clone());
+            }
         }
+        instances
+    }
 
-        let mut operand = operands[0].clone();
+    /// Whether the predicate of `block` depends on a block that has been skipped, whose own
+    /// predicate is unknown.
+    fn follows_skipped_block(&self, block: &Name) -> bool {
+        let parents = self.parents.get(block).into_iter().flatten();
+        let mut sources = self
+            .equivalent
+            .get(block)
+            .into_iter()
+            .chain(parents.map(|p| p.name()));
+        sources.any(|source| !self.predicates.contains_key(source))
+    }
 
-        for cur in operands.iter().skip(1) {
-            let condition_name = self.get_name_id(
-                "COND",
-                format!("{}_U_{}", operand.to_circom(), cur.to_circom()),
-            );
-            let condition = self.signals.get_reference(condition_name);
-            let instr = ConstraintGenerationAssigment {
-                left: condition.clone(),
-                right: Expression::BinaryOr(BinaryOr {
-                    a: operand,
-                    b: cur.clone(),
-                }),
-            };
-            instrs.push(instr.into());
-            operand = CircomOperand::Reference(condition);
+    /// Declares the execution predicate of the block instance `block`, `None` when it is
+    /// always executed. Its parents have all been walked before. A block post-dominating its
+    /// immediate dominator shares its predicate; any other block is executed when one of its
+    /// incoming edges is taken. The CFG is acyclic, so at most one of them is: the predicate
+    /// is the sum of the edge predicates, keeping the constraints linear in the CFG size.
+    fn declare_condition(
+        &mut self,
+        block: &Name,
+        instrs: &mut InstructionConsumer,
+    ) -> TranslationResult<Option<CircomOperand>> {
+        if let Some(predicate) = self.predicates.get(block) {
+            return Ok(predicate.clone());
         }
-        self.declared_conditions
-            .insert(branch.clone(), operand.clone());
-        Some(operand)
+        let predicate = match self.equivalent.get(block) {
+            Some(dominator) => match self.predicates.get(dominator) {
+                Some(predicate) => predicate.clone(),
+                None => unsupported!("The predicate of the dominator {dominator} is unknown"),
+            },
+            None => {
+                let mut sources: Vec<Name> = vec![];
+                for parent in self.parents.get(block).into_iter().flatten() {
+                    if !sources.contains(parent.name()) {
+                        sources.push(parent.name().clone());
+                    }
+                }
+                let mut edges = Some(vec![]);
+                for from in &sources {
+                    let edge = self.edge_predicate(from, block, instrs)?;
+                    edges = edges.zip(edge).map(|(mut edges, edge)| {
+                        edges.push(edge);
+                        edges
+                    });
+                }
+                match edges {
+                    _ if sources.is_empty() => self.root_condition.clone(),
+                    None => None,
+                    Some(edges) if edges.len() == 1 => Some(edges[0].clone()),
+                    Some(edges) => {
+                        let name =
+                            self.get_name_id("COND", format!("{}_EXEC", block.to_simple_string()));
+                        Some(self.condition_signal(name, Expression::Sum(edges), instrs)?)
+                    }
+                }
+            }
+        };
+        self.predicates.insert(block.clone(), predicate.clone());
+        Ok(predicate)
     }
 
-    fn simple_branch_operand(
+    /// Condition under which control flows from `from` into the current block.
+    fn edge_condition(
         &mut self,
-        branch: &SimpleBranch,
+        from: &Name,
         instrs: &mut InstructionConsumer,
-    ) -> Option<CircomOperand> {
-        if branch.0.is_empty() {
-            return None;
-        }
-
-        if let Some(condition) = self.declared_conditions.get(&branch.into()) {
-            return Some(condition.clone());
-        }
-        let BranchNode(condition_operand, t_or_f) = branch.leaf_condition().unwrap();
-        let condition_operand = if t_or_f {
-            condition_operand
-        } else {
-            let false_condition_operand = self
-                .signals
-                .get_reference(format!("{}F", condition_operand.to_circom()));
-            let i = ConstraintGenerationAssigment {
-                left: false_condition_operand.clone(),
-                right: Expression::BinaryOperation(BinaryOperation {
-                    left: CircomOperand::Constant(1),
-                    op: BinaryOperationType::Sub,
-                    right: condition_operand,
-                }),
-            };
-            instrs.push(i.into());
-            CircomOperand::Reference(false_condition_operand)
-        };
+    ) -> TranslationResult<Option<CircomOperand>> {
+        let to = self.current_block.clone();
+        self.edge_predicate(from, &to, instrs)
+    }
 
-        let parent = branch.parent();
-        let parent_condition = match self.declared_conditions.get(&parent.into()) {
-            Some(c) => c.clone(),
-            None => return Some(condition_operand),
+    /// Predicate of the edge from `from` into `to`: the predicate of `from` and the branch
+    /// condition, `None` when the edge is always taken.
+    fn edge_predicate(
+        &mut self,
+        from: &Name,
+        to: &Name,
+        instrs: &mut InstructionConsumer,
+    ) -> TranslationResult<Option<CircomOperand>> {
+        let key = (from.clone(), to.clone());
+        if let Some(predicate) = self.edge_predicates.get(&key) {
+            return Ok(predicate.clone());
+        }
+        let edges: Vec<ParentInfo> = self
+            .parents
+            .get(to)
+            .into_iter()
+            .flatten()
+            .filter(|p| p.name() == from)
+            .cloned()
+            .collect();
+        if edges.is_empty() {
+            unsupported!("{from} is not a predecessor of {to}");
+        }
+        let mut branches = vec![];
+        for edge in &edges {
+            match edge {
+                ParentInfo::TrueBranch(_, condition) => branches.push(condition.clone()),
+                ParentInfo::FalseBranch(_, condition @ CircomOperand::Constant(_)) => {
+                    branches.push(CircomOperand::constant(condition.is_constant(0) as u32, 1));
+                }
+                ParentInfo::FalseBranch(_, condition) => {
+                    let negation = self
+                        .signals
+                        .get_reference(format!("{}_NOT", condition.to_circom()));
+                    let i = ConstraintGenerationAssigment {
+                        left: negation.clone(),
+                        right: Expression::BinaryOperation(BinaryOperation {
+                            left: CircomOperand::constant(1u32, 1),
+                            op: BinaryOperationType::Sub,
+                            right: condition.clone(),
+                        }),
+                    };
+                    instrs.push(i.into())?;
+                    branches.push(CircomOperand::Reference(negation));
+                }
+                ParentInfo::Merge(_) => {}
+            }
+        }
+        let Some(source) = self.predicates.get(from).cloned() else {
+            unsupported!("The predicate of {from} is unknown");
+        };
+        let edge_name = format!("{}_{}", from.to_simple_string(), to.to_simple_string());
+        // Several destinations of a `switch` (or both of a `br`) may be the same block.
+        let branch = match &branches[..] {
+            _ if branches.len() < edges.len() => None,
+            [branch] => Some(branch.clone()),
+            _ => {
+                let name = self.get_name_id("COND", format!("{edge_name}_ANY"));
+                Some(self.condition_signal(name, Expression::Sum(branches), instrs)?)
+            }
+        };
+        // Constant conditions (`br i1 true`) are folded: the edge is always or never taken.
+        let predicate = match (source, branch) {
+            (source, None) => source,
+            (source, Some(branch)) if branch.is_constant(1) => source,
+            (_, Some(branch)) if branch.is_constant(0) => Some(branch),
+            (None, Some(branch)) => Some(branch),
+            (Some(source), Some(branch)) => {
+                let name = self.get_name_id("COND", edge_name);
+                let product = Expression::BinaryOperation(BinaryOperation {
+                    left: source,
+                    op: BinaryOperationType::Mul,
+                    right: branch,
+                });
+                Some(self.condition_signal(name, product, instrs)?)
+            }
         };
+        self.edge_predicates.insert(key, predicate.clone());
+        Ok(predicate)
+    }
 
-        let cond_id = self.get_name_id("COND", branch.condition_operand_name());
-        let condition = self.signals.get_reference(cond_id);
+    fn condition_signal(
+        &mut self,
+        name: String,
+        value: Expression,
+        instrs: &mut InstructionConsumer,
+    ) -> TranslationResult<CircomOperand> {
+        let condition = self.signals.get_reference(name);
         let i = ConstraintGenerationAssigment {
             left: condition.clone(),
-            right: Expression::BinaryOperation(BinaryOperation {
-                left: parent_condition.clone(),
-                op: BinaryOperationType::Mul,
-                right: condition_operand,
-            }),
+            right: value,
         };
-        instrs.push(i.into());
-        Some(CircomOperand::Reference(condition))
+        instrs.push(i.into())?;
+        Ok(CircomOperand::Reference(condition))
     }
 
     fn get_name_id(&mut self, type_: impl Into<String>, name: String) -> String {
@@ -1,7 +1,13 @@
 use llvm_ir::function::Parameter;
 use std::collections::HashMap;
 
-use crate::instructions::*;
+use crate::{
+    instructions::*,
+    ir_circom::{
+        diagnostics::{TranslationResult, unsupported},
+        wide::value_signals,
+    },
+};
 
 pub struct SignalDeclarations {
     declared: HashMap<String, SignalDeclaration>,
@@ -12,9 +18,8 @@ impl SignalDeclarations {
     pub fn new(parameters: &[Parameter]) -> Self {
         let declared = parameters
             .iter()
-            .map(|p| p.name.to_simple_string())
-            // We know that the self argument cannot be used in our function
-            .filter(|s| s != "self")
+            .filter_map(|p| Some((p.name.to_simple_string(), p.ty.integer_bits()?)))
+            .flat_map(|(name, bits)| value_signals(&name, bits))
             .map(|s| (s.clone(), SignalDeclaration::Input(s)))
             .collect();
         Self {
@@ -22,43 +27,63 @@ impl SignalDeclarations {
             mutable: HashMap::new(),
         }
     }
+    /// Whether the parameter is a scalar input signal of the template. Pointer parameters are
+    /// declared as input arrays once the accesses through them are known.
+    pub fn is_input(parameter: &Parameter) -> bool {
+        parameter.ty.integer_bits().is_some()
+    }
     pub fn get_reference(&mut self, name: String) -> Reference {
         self.declared
             .entry(name.clone())
             .or_insert_with(|| SignalDeclaration::Private(name.to_string()))
             .reference()
     }
-    pub fn declare_mutable_reference(&mut self, name: String) -> Reference {
-        if let Some(_) = self.mutable.insert(name.clone(), 0) {
-            panic!("Mutable reference already declared")
+    pub fn declare_input(&mut self, name: String) -> Reference {
+        self.declared
+            .entry(name.clone())
+            .or_insert_with(|| SignalDeclaration::Input(name.to_string()))
+            .reference()
+    }
+    pub fn declare_input_array(&mut self, name: String, len: usize) -> Reference {
+        self.declared
+            .entry(name.clone())
+            .or_insert_with(|| SignalDeclaration::InputArray(name.to_string(), len))
+            .reference()
+    }
+    pub fn declare_mutable_reference(&mut self, name: String) -> TranslationResult<Reference> {
+        if self.mutable.contains_key(&name) {
+            unsupported!("Declaring the mutable signal {name} twice");
         }
-        self.get_reference(format!("{name}_m0"))
+        self.mutable.insert(name.clone(), 0);
+        Ok(self.get_reference(format!("{name}_m0")))
     }
-    pub fn increment_mutable_reference(&mut self, name: String) -> Reference {
-        let count = {
-            let i = self.mutable.get_mut(&name).unwrap();
-            *i += 1;
-            *i
+    pub fn increment_mutable_reference(&mut self, name: String) -> TranslationResult<Reference> {
+        let Some(count) = self.mutable.get_mut(&name) else {
+            unsupported!("Writing the undeclared mutable signal {name}");
         };
-        self.get_reference(format!("{name}_m{count}"))
+        *count += 1;
+        let count = *count;
+        Ok(self.get_reference(format!("{name}_m{count}")))
     }
-    pub fn read_mutable_reference(&mut self, name: String) -> CircomOperand {
-        let count = *self.mutable.get(&name).unwrap();
-        CircomOperand::Reference(self.get_reference(format!("{name}_m{count}")))
+    pub fn read_mutable_reference(&mut self, name: String) -> TranslationResult<CircomOperand> {
+        let Some(count) = self.mutable.get(&name) else {
+            unsupported!("Reading the undeclared mutable signal {name}");
+        };
+        let reference = self.get_reference(format!("{name}_m{count}"));
+        Ok(CircomOperand::Reference(reference))
     }
     pub fn output_signal_name(&self) -> String {
         "OUTPUT_".into()
     }
-    pub fn output_signal_final_reference(&mut self) -> Reference {
-        let name = self.output_signal_name();
+    pub fn enabled_signal_name(&self) -> String {
+        "ENABLED_".into()
+    }
+    pub fn declare_output(&mut self, name: String) -> Reference {
         self.declared
             .entry(name.clone())
             .or_insert_with(|| SignalDeclaration::Output(name.to_string()))
             .reference()
     }
-    pub fn read_output_signal(&mut self) -> CircomOperand {
-        self.read_mutable_reference(self.output_signal_name())
-    }
     pub fn signals_instructions(&self) -> Vec<CircomInstr> {
         self.declared
             .values()
@@ -0,0 +1,585 @@
+use llvm_ir::{Instruction, IntPredicate, Name, Operand, TypeRef, instruction::Call};
+
+use crate::{
+    instructions::*,
+    ir_circom::{
+        Structure,
+        diagnostics::{TranslationResult, unsupported},
+        instruction_handler::{aggregate_field, binary_component, enabled_operand},
+    },
+};
+
+/// Integers wider than this do not fit the arithmetic of a single field element. They are
+/// split into limbs of this many bits, least significant first: the limb `i` of the value `x`
+/// is the signal `x_L{i}`, lowered with the `circuit/llvm/wide.circom` templates.
+pub const LIMB_BITS: u32 = 64;
+
+pub fn is_wide(bits: u32) -> bool {
+    bits > LIMB_BITS
+}
+
+pub fn limb_count(bits: u32) -> u32 {
+    bits.div_ceil(LIMB_BITS)
+}
+
+/// Bit width of the limb `limb` of an integer of `bits` bits (the whole integer when narrow).
+pub fn limb_bits(bits: u32, limb: u32) -> u32 {
+    match limb + 1 == limb_count(bits) {
+        true => bits - LIMB_BITS * limb,
+        false => LIMB_BITS,
+    }
+}
+
+pub fn limb_name(name: &str, limb: u32) -> String {
+    format!("{name}_L{limb}")
+}
+
+/// Signals holding the value `name` of `bits` bits: the value itself, or its limbs.
+pub fn value_signals(name: &str, bits: u32) -> Vec<String> {
+    match is_wide(bits) {
+        false => vec![name.to_string()],
+        true => (0..limb_count(bits)).map(|i| limb_name(name, i)).collect(),
+    }
+}
+
+/// Fields of a component holding a value of `bits` bits: `field` itself, or the array of its
+/// limbs.
+pub fn limb_fields(field: &str, bits: u32) -> Vec<String> {
+    match is_wide(bits) {
+        false => vec![field.to_string()],
+        true => (0..limb_count(bits))
+            .map(|i| format!("{field}[{i}]"))
+            .collect(),
+    }
+}
+
+/// `operand` as seen from the current block instance, split into limbs when wide.
+pub fn operand_limbs(
+    structure: &Structure,
+    operand: &Operand,
+) -> TranslationResult<Vec<CircomOperand>> {
+    operand_limbs_in(structure, operand, &structure.current_block)
+}
+
+/// `operand` as seen from the block instance `instance`, split into limbs when wide.
+pub fn operand_limbs_in(
+    structure: &Structure,
+    operand: &Operand,
+    instance: &Name,
+) -> TranslationResult<Vec<CircomOperand>> {
+    let Some(bits) = operand.integer_bits().filter(|bits| is_wide(*bits)) else {
+        return Ok(vec![structure.operand_in(operand, instance)?]);
+    };
+    let limbs = match operand {
+        Operand::LocalOperand { name, .. } => {
+            value_signals(&structure.local_name_in(name, instance)?, bits)
+                .into_iter()
+                .map(|limb| CircomOperand::Reference(Reference::SignalRef(limb)))
+                .collect()
+        }
+        other => match structure.operand_in(other, instance)? {
+            CircomOperand::Constant(constant) => (0..limb_count(bits))
+                .map(|limb| constant_limb(&constant, limb))
+                .collect(),
+            reference => unsupported!("Wide operand {reference:?}"),
+        },
+    };
+    Ok(limbs)
+}
+
+/// The limb `limb` of a wide constant.
+pub fn constant_limb(constant: &IntConstant, limb: u32) -> CircomOperand {
+    let value = &constant.value >> (LIMB_BITS * limb);
+    CircomOperand::constant(value, limb_bits(constant.bits, limb))
+}
+
+fn is_wide_operand(operand: &Operand) -> bool {
+    operand.integer_bits().is_some_and(is_wide)
+}
+
+fn is_wide_type(ty: &TypeRef) -> bool {
+    ty.integer_bits().is_some_and(is_wide)
+}
+
+/// Lowers the instructions on integers wider than [`LIMB_BITS`], `None` for the instructions
+/// without wide operands or results. Phis, selects, aggregates and memory accesses handle
+/// the limbs of their values themselves.
+pub fn handle_wide_instruction(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    instruction: &Instruction,
+) -> TranslationResult<Option<Vec<CircomInstr>>> {
+    let instrs = match instruction {
+        Instruction::Add(add) if is_wide_operand(&add.operand0) => handle_wide_arithmetic(
+            structure,
+            condition,
+            ("WideAdd", "ADD"),
+            &add.dest,
+            (&add.operand0, &add.operand1),
+            &[add.nuw, add.nsw],
+        ),
+        Instruction::Sub(sub) if is_wide_operand(&sub.operand0) => handle_wide_arithmetic(
+            structure,
+            condition,
+            ("WideSub", "SUB"),
+            &sub.dest,
+            (&sub.operand0, &sub.operand1),
+            &[sub.nuw, sub.nsw],
+        ),
+        // Neither the signed overflow of a product nor the flags of a shift are checked by
+        // the wide templates, so the instructions relying on them are rejected.
+        Instruction::Mul(mul) if is_wide_operand(&mul.operand0) && mul.nsw => {
+            unsupported!("`mul nsw` on integers wider than {LIMB_BITS} bits: {instruction}")
+        }
+        Instruction::Shl(shl) if is_wide_operand(&shl.operand0) && (shl.nuw || shl.nsw) => {
+            unsupported!(
+                "`shl nuw`/`shl nsw` on integers wider than {LIMB_BITS} bits: {instruction}"
+            )
+        }
+        Instruction::LShr(lshr) if is_wide_operand(&lshr.operand0) && lshr.exact => {
+            unsupported!("`lshr exact` on integers wider than {LIMB_BITS} bits: {instruction}")
+        }
+        Instruction::AShr(ashr) if is_wide_operand(&ashr.operand0) && ashr.exact => {
+            unsupported!("`ashr exact` on integers wider than {LIMB_BITS} bits: {instruction}")
+        }
+        Instruction::Mul(mul) if is_wide_operand(&mul.operand0) => handle_wide_arithmetic(
+            structure,
+            condition,
+            ("WideMul", "MUL"),
+            &mul.dest,
+            (&mul.operand0, &mul.operand1),
+            &[mul.nuw],
+        ),
+        Instruction::And(and) if is_wide_operand(&and.operand0) => handle_wide_bitwise(
+            structure,
+            ("IntAnd", "AND"),
+            &and.dest,
+            (&and.operand0, &and.operand1),
+        ),
+        Instruction::Or(or) if is_wide_operand(&or.operand0) => handle_wide_bitwise(
+            structure,
+            ("IntOr", "OR"),
+            &or.dest,
+            (&or.operand0, &or.operand1),
+        ),
+        Instruction::Xor(xor) if is_wide_operand(&xor.operand0) => handle_wide_bitwise(
+            structure,
+            ("IntXor", "XOR"),
+            &xor.dest,
+            (&xor.operand0, &xor.operand1),
+        ),
+        Instruction::Shl(shl) if is_wide_operand(&shl.operand0) => handle_wide_shift(
+            structure,
+            condition,
+            ("SHL", 0),
+            &shl.dest,
+            (&shl.operand0, &shl.operand1),
+        ),
+        Instruction::LShr(lshr) if is_wide_operand(&lshr.operand0) => handle_wide_shift(
+            structure,
+            condition,
+            ("SHR", 1),
+            &lshr.dest,
+            (&lshr.operand0, &lshr.operand1),
+        ),
+        Instruction::AShr(ashr) if is_wide_operand(&ashr.operand0) => handle_wide_shift(
+            structure,
+            condition,
+            ("SHR", 2),
+            &ashr.dest,
+            (&ashr.operand0, &ashr.operand1),
+        ),
+        Instruction::ICmp(icmp) if is_wide_operand(&icmp.operand0) => {
+            handle_wide_icmp(structure, icmp)
+        }
+        Instruction::ZExt(zext) if is_wide_type(&zext.to_type) => handle_wide_zext(structure, zext),
+        Instruction::SExt(sext) if is_wide_type(&sext.to_type) => handle_wide_sext(structure, sext),
+        Instruction::Trunc(trunc) if is_wide_operand(&trunc.operand) => {
+            handle_wide_trunc(structure, trunc)
+        }
+        Instruction::UDiv(_)
+        | Instruction::SDiv(_)
+        | Instruction::URem(_)
+        | Instruction::SRem(_)
+            if instruction_operands_wide(instruction) =>
+        {
+            unsupported!("Division of integers wider than {LIMB_BITS} bits: {instruction}")
+        }
+        _ => return Ok(None),
+    };
+    instrs.map(Some)
+}
+
+/// Whether `call` takes or returns integers wider than [`LIMB_BITS`].
+pub fn is_wide_call(structure: &Structure, call: &Call) -> bool {
+    call.arguments.iter().any(|(a, _)| is_wide_operand(a))
+        || is_wide_type(&structure.module.type_of(call))
+}
+
+/// Intrinsics with a lowering on wide integers, by name prefix, with their template and
+/// whether they are signed.
+const WIDE_INTRINSICS: &[(&str, &str, u32)] = &[
+    ("llvm.uadd.with.overflow.", "WideAddWithOverflow", 0),
+    ("llvm.sadd.with.overflow.", "WideAddWithOverflow", 1),
+    ("llvm.usub.with.overflow.", "WideSubWithOverflow", 0),
+    ("llvm.ssub.with.overflow.", "WideSubWithOverflow", 1),
+    ("llvm.umul.with.overflow.", "WideMulWithOverflow", 0),
+    ("llvm.smul.with.overflow.", "WideMulWithOverflow", 1),
+    ("llvm.umin.", "WideMin", 0),
+    ("llvm.smin.", "WideMin", 1),
+    ("llvm.umax.", "WideMax", 0),
+    ("llvm.smax.", "WideMax", 1),
+];
+
+/// Lowers a call to an intrinsic on wide integers. `llvm.*.with.overflow` assign the limbs of
+/// the wrapped result and the overflow flag to the two fields of the destination aggregate.
+pub fn handle_wide_intrinsic(
+    structure: &mut Structure,
+    call: &Call,
+    name: &str,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let Some((_, component, signed)) = WIDE_INTRINSICS
+        .iter()
+        .find(|(prefix, _, _)| name.starts_with(prefix))
+    else {
+        unsupported!("Intrinsic {name} on integers wider than {LIMB_BITS} bits: {call}");
+    };
+    let [(operand0, _), (operand1, _)] = &call.arguments[..] else {
+        unsupported!("{call}");
+    };
+    let Some(bits) = operand0.integer_bits() else {
+        unsupported!("{call}");
+    };
+    let Some(dest) = &call.dest else {
+        return Ok(vec![]);
+    };
+    let dest = structure.value_name(dest)?;
+    let component = ComponentInstatiation {
+        name: format!("{dest}_INTR"),
+        component: component.to_string(),
+        args: vec![
+            limb_count(bits),
+            limb_bits(bits, limb_count(bits) - 1),
+            *signed,
+        ],
+    };
+    let mut instrs = vec![component.clone().into()];
+    instrs.extend(wire_limbs(structure, &component, &[operand0, operand1])?);
+    if !name.contains(".with.overflow.") {
+        instrs.extend(limb_outputs(structure, &component, &dest, bits));
+        return Ok(instrs);
+    }
+    let result = aggregate_field(&dest, &[0]);
+    instrs.extend(limb_outputs(structure, &component, &result, bits));
+    let overflow = ConstraintGenerationAssigment {
+        left: structure
+            .signals
+            .get_reference(aggregate_field(&dest, &[1])),
+        right: Expression::Operand(CircomOperand::Reference(component.field("overflow"))),
+    };
+    instrs.push(overflow.into());
+    Ok(instrs)
+}
+
+fn instruction_operands_wide(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::UDiv(i) => is_wide_operand(&i.operand0),
+        Instruction::SDiv(i) => is_wide_operand(&i.operand0),
+        Instruction::URem(i) => is_wide_operand(&i.operand0),
+        Instruction::SRem(i) => is_wide_operand(&i.operand0),
+        _ => false,
+    }
+}
+
+/// Wires `operands` to the limbs of the `in[j][i]` inputs of `component`.
+fn wire_limbs(
+    structure: &Structure,
+    component: &ComponentInstatiation,
+    operands: &[&Operand],
+) -> TranslationResult<Vec<CircomInstr>> {
+    let mut instrs = vec![];
+    for (j, operand) in operands.iter().enumerate() {
+        for (i, limb) in operand_limbs(structure, operand)?.into_iter().enumerate() {
+            let i = ConstraintGenerationAssigment {
+                left: component.field(&format!("in[{j}][{i}]")),
+                right: Expression::Operand(limb),
+            };
+            instrs.push(i.into());
+        }
+    }
+    Ok(instrs)
+}
+
+/// Assigns the limbs of `dest` from the `out[i]` outputs of `component`.
+fn limb_outputs(
+    structure: &mut Structure,
+    component: &ComponentInstatiation,
+    dest: &str,
+    bits: u32,
+) -> Vec<CircomInstr> {
+    value_signals(dest, bits)
+        .into_iter()
+        .enumerate()
+        .map(|(i, limb)| {
+            ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(limb),
+                right: Expression::Operand(CircomOperand::Reference(
+                    component.field(&format!("out[{i}]")),
+                )),
+            }
+            .into()
+        })
+        .collect()
+}
+
+fn enabled_input(
+    component: &ComponentInstatiation,
+    condition: &Option<CircomOperand>,
+) -> CircomInstr {
+    ConstraintGenerationAssigment {
+        left: component.field("enabled"),
+        right: Expression::Operand(enabled_operand(condition)),
+    }
+    .into()
+}
+
+fn handle_wide_arithmetic(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    (component, suffix): (&str, &str),
+    dest: &Name,
+    (operand0, operand1): (&Operand, &Operand),
+    flags: &[bool],
+) -> TranslationResult<Vec<CircomInstr>> {
+    let bits = operand0.integer_bits().unwrap();
+    let dest = structure.value_name(dest)?;
+    let component = ComponentInstatiation {
+        name: format!("{dest}_{suffix}"),
+        component: component.to_string(),
+        args: [limb_count(bits), limb_bits(bits, limb_count(bits) - 1)]
+            .into_iter()
+            .chain(flags.iter().map(|flag| *flag as u32))
+            .collect(),
+    };
+    let mut instrs = vec![component.clone().into()];
+    instrs.extend(wire_limbs(structure, &component, &[operand0, operand1])?);
+    instrs.push(enabled_input(&component, condition));
+    instrs.extend(limb_outputs(structure, &component, &dest, bits));
+    Ok(instrs)
+}
+
+/// Bitwise operations are computed limb by limb.
+fn handle_wide_bitwise(
+    structure: &mut Structure,
+    (component, suffix): (&str, &str),
+    dest: &Name,
+    (operand0, operand1): (&Operand, &Operand),
+) -> TranslationResult<Vec<CircomInstr>> {
+    let bits = operand0.integer_bits().unwrap();
+    let dest = structure.value_name(dest)?;
+    let limbs0 = operand_limbs(structure, operand0)?;
+    let limbs1 = operand_limbs(structure, operand1)?;
+    let mut instrs = vec![];
+    for (i, (limb0, limb1)) in limbs0.into_iter().zip(limbs1).enumerate() {
+        let component = ComponentInstatiation {
+            name: format!("{dest}_{suffix}{i}"),
+            component: component.to_string(),
+            args: vec![limb_bits(bits, i as u32)],
+        };
+        let out = CircomOperand::Reference(component.field("out"));
+        instrs.extend(binary_component(component, limb0, limb1));
+        let res = ConstraintGenerationAssigment {
+            left: structure.signals.get_reference(limb_name(&dest, i as u32)),
+            right: Expression::Operand(out),
+        };
+        instrs.push(res.into());
+    }
+    Ok(instrs)
+}
+
+fn handle_wide_shift(
+    structure: &mut Structure,
+    condition: &Option<CircomOperand>,
+    (suffix, kind): (&str, u32),
+    dest: &Name,
+    (operand0, operand1): (&Operand, &Operand),
+) -> TranslationResult<Vec<CircomInstr>> {
+    let bits = operand0.integer_bits().unwrap();
+    let dest = structure.value_name(dest)?;
+    let component = ComponentInstatiation {
+        name: format!("{dest}_{suffix}"),
+        component: "WideShift".to_string(),
+        args: vec![
+            limb_count(bits),
+            limb_bits(bits, limb_count(bits) - 1),
+            kind,
+        ],
+    };
+    let mut instrs = vec![component.clone().into()];
+    for (field, operand) in [("in", operand0), ("amount", operand1)] {
+        for (i, limb) in operand_limbs(structure, operand)?.into_iter().enumerate() {
+            let i = ConstraintGenerationAssigment {
+                left: component.field(&format!("{field}[{i}]")),
+                right: Expression::Operand(limb),
+            };
+            instrs.push(i.into());
+        }
+    }
+    instrs.push(enabled_input(&component, condition));
+    instrs.extend(limb_outputs(structure, &component, &dest, bits));
+    Ok(instrs)
+}
+
+/// Every ordering is a `WideLessThan` on the operands, possibly swapped and negated.
+fn handle_wide_icmp(
+    structure: &mut Structure,
+    icmp: &llvm_ir::instruction::ICmp,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let bits = icmp.operand0.integer_bits().unwrap();
+    let name = structure.value_name(&icmp.dest)?;
+    let (a, b) = (&icmp.operand0, &icmp.operand1);
+    let (signed, (a, b), negate) = match icmp.predicate {
+        IntPredicate::EQ | IntPredicate::NE => (None, (a, b), icmp.predicate == IntPredicate::NE),
+        IntPredicate::ULT => (Some(0), (a, b), false),
+        IntPredicate::UGT => (Some(0), (b, a), false),
+        IntPredicate::ULE => (Some(0), (b, a), true),
+        IntPredicate::UGE => (Some(0), (a, b), true),
+        IntPredicate::SLT => (Some(1), (a, b), false),
+        IntPredicate::SGT => (Some(1), (b, a), false),
+        IntPredicate::SLE => (Some(1), (b, a), true),
+        IntPredicate::SGE => (Some(1), (a, b), true),
+    };
+    let component = match signed {
+        None => ComponentInstatiation {
+            name: format!("{name}_EQ"),
+            component: "WideIsEqual".to_string(),
+            args: vec![limb_count(bits)],
+        },
+        Some(signed) => ComponentInstatiation {
+            name: format!("{name}_CMP"),
+            component: "WideLessThan".to_string(),
+            args: vec![
+                limb_count(bits),
+                limb_bits(bits, limb_count(bits) - 1),
+                signed,
+            ],
+        },
+    };
+    let out = CircomOperand::Reference(component.field("out"));
+    let mut instrs = vec![component.clone().into()];
+    instrs.extend(wire_limbs(structure, &component, &[a, b])?);
+    let res = ConstraintGenerationAssigment {
+        left: structure.signals.get_reference(name),
+        right: if negate {
+            Expression::BinaryOperation(BinaryOperation {
+                left: CircomOperand::constant(1u32, 1),
+                op: BinaryOperationType::Sub,
+                right: out,
+            })
+        } else {
+            Expression::Operand(out)
+        },
+    };
+    instrs.push(res.into());
+    Ok(instrs)
+}
+
+/// The limbs of the source, followed by zero limbs.
+fn handle_wide_zext(
+    structure: &mut Structure,
+    zext: &llvm_ir::instruction::ZExt,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let bits = zext.to_type.integer_bits().unwrap();
+    let dest = structure.value_name(&zext.dest)?;
+    let limbs = operand_limbs(structure, &zext.operand)?;
+    Ok(value_signals(&dest, bits)
+        .into_iter()
+        .enumerate()
+        .map(|(i, limb)| {
+            ConstraintGenerationAssigment {
+                left: structure.signals.get_reference(limb),
+                right: Expression::Operand(
+                    limbs
+                        .get(i)
+                        .cloned()
+                        .unwrap_or(CircomOperand::constant(0u32, LIMB_BITS)),
+                ),
+            }
+            .into()
+        })
+        .collect())
+}
+
+fn handle_wide_sext(
+    structure: &mut Structure,
+    sext: &llvm_ir::instruction::SExt,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let (Some(from), Some(to)) = (sext.operand.integer_bits(), sext.to_type.integer_bits()) else {
+        unsupported!("sext on non-integer operands: {sext}");
+    };
+    let dest = structure.value_name(&sext.dest)?;
+    let component = ComponentInstatiation {
+        name: format!("{dest}_SEXT"),
+        component: "WideSignExtend".to_string(),
+        args: vec![
+            limb_count(from),
+            limb_bits(from, limb_count(from) - 1),
+            limb_count(to),
+            limb_bits(to, limb_count(to) - 1),
+        ],
+    };
+    let mut instrs = vec![component.clone().into()];
+    for (i, limb) in operand_limbs(structure, &sext.operand)?
+        .into_iter()
+        .enumerate()
+    {
+        let i = ConstraintGenerationAssigment {
+            left: component.field(&format!("in[{i}]")),
+            right: Expression::Operand(limb),
+        };
+        instrs.push(i.into());
+    }
+    instrs.extend(limb_outputs(structure, &component, &dest, to));
+    Ok(instrs)
+}
+
+/// Keeps the low limbs of the source; the most significant kept limb drops its high bits
+/// when it is narrower than the source limb.
+fn handle_wide_trunc(
+    structure: &mut Structure,
+    trunc: &llvm_ir::instruction::Trunc,
+) -> TranslationResult<Vec<CircomInstr>> {
+    let (Some(from), Some(to)) = (trunc.operand.integer_bits(), trunc.to_type.integer_bits())
+    else {
+        unsupported!("trunc on non-integer operands: {trunc}");
+    };
+    let dest = structure.value_name(&trunc.dest)?;
+    let limbs = operand_limbs(structure, &trunc.operand)?;
+    let mut instrs = vec![];
+    for (i, limb) in value_signals(&dest, to).into_iter().enumerate() {
+        let (source_bits, bits) = (limb_bits(from, i as u32), limb_bits(to, i as u32));
+        let value = match source_bits == bits {
+            true => limbs[i].clone(),
+            false => {
+                let component = ComponentInstatiation {
+                    name: format!("{dest}_TRUNC{i}"),
+                    component: "Trunc".to_string(),
+                    args: vec![source_bits, bits],
+                };
+                let input = ConstraintGenerationAssigment {
+                    left: component.field("in"),
+                    right: Expression::Operand(limbs[i].clone()),
+                };
+                let out = CircomOperand::Reference(component.field("out"));
+                instrs.extend([component.into(), input.into()]);
+                out
+            }
+        };
+        let i = ConstraintGenerationAssigment {
+            left: structure.signals.get_reference(limb),
+            right: Expression::Operand(value),
+        };
+        instrs.push(i.into());
+    }
+    Ok(instrs)
+}
@@ -0,0 +1,435 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, c_char},
+    ops::Deref,
+    path::Path,
+    ptr,
+};
+
+use llvm_ir::{Module, Name};
+use llvm_sys::{
+    LLVMOpcode, LLVMTypeKind,
+    bit_reader::LLVMParseBitcodeInContext2,
+    core::*,
+    ir_reader::LLVMParseIRInContext,
+    prelude::{LLVMContextRef, LLVMModuleRef, LLVMTypeRef, LLVMValueRef},
+    target::{
+        LLVMABISizeOfType, LLVMByteOrder, LLVMByteOrdering, LLVMGetModuleDataLayout,
+        LLVMOffsetOfElement, LLVMTargetDataRef,
+    },
+};
+use num_bigint::{BigInt, BigUint};
+
+use crate::{
+    instructions::IntConstant,
+    ir_circom::{
+        diagnostics::{TranslationResult, unsupported},
+        wide::{LIMB_BITS, is_wide},
+    },
+};
+
+/// A module, with what `llvm-ir` does not expose about its constants: the value of the
+/// integers wider than 64 bits, of which it only keeps the low 64 bits, and the offset of the
+/// constant `getelementptr` expressions, whose source element type it drops. Both are read
+/// from a second parse of the module with the LLVM C API, and looked up by where the constant
+/// is used.
+pub struct IrModule {
+    module: Module,
+    /// `llvm-ir` takes modules without a data layout for big-endian, while LLVM defaults to
+    /// little-endian: the byte order is LLVM's.
+    big_endian: bool,
+    /// Constants of each instruction, by function, block and instruction index.
+    instructions: HashMap<(usize, usize, usize), InstructionConstants>,
+    /// Wide integers of the global initializers, by global index and path in the initializer.
+    initializers: HashMap<(usize, Vec<usize>), BigUint>,
+}
+
+/// Where a constant is used.
+pub enum ConstantScope<'s> {
+    /// An operand of the instruction `instruction` of the block `block` of `function`, by
+    /// index. The terminator comes after the instructions of its block.
+    Instruction {
+        function: &'s str,
+        block: usize,
+        instruction: usize,
+    },
+    /// The initializer of `global`, at the element indices `path` of its aggregates.
+    Initializer { global: &'s Name, path: &'s [usize] },
+}
+
+#[derive(Default)]
+struct InstructionConstants {
+    /// Wide integers of the operands, with their width, modulo 2^N.
+    wide: Vec<(u32, BigUint)>,
+    geps: Vec<ConstantGep>,
+}
+
+/// A constant `getelementptr` into a global, possibly through other constant ones.
+struct ConstantGep {
+    /// Index of the global the pointer is based on.
+    global: usize,
+    indices: Vec<i64>,
+    /// Offset in bytes added to the base pointer.
+    offset: i64,
+}
+
+impl IrModule {
+    /// Parses the textual IR `text`.
+    pub fn from_ir_str(text: &str) -> Result<Self, String> {
+        let module = Module::from_ir_str(text)?;
+        let buffer = unsafe {
+            LLVMCreateMemoryBufferWithMemoryRangeCopy(
+                text.as_ptr().cast(),
+                text.len(),
+                c"".as_ptr(),
+            )
+        };
+        Self::with_constants(module, |context, out| unsafe {
+            let mut message = ptr::null_mut();
+            // The parser takes ownership of the buffer.
+            match LLVMParseIRInContext(context, buffer, out, &mut message) {
+                0 => Ok(()),
+                _ => Err(take_message(message)),
+            }
+        })
+    }
+
+    /// Parses the bitcode file at `path`.
+    pub fn from_bc_path(path: &Path) -> Result<Self, String> {
+        let module = Module::from_bc_path(path)?;
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let buffer = unsafe {
+            LLVMCreateMemoryBufferWithMemoryRangeCopy(
+                bytes.as_ptr().cast(),
+                bytes.len(),
+                c"".as_ptr(),
+            )
+        };
+        Self::with_constants(module, |context, out| unsafe {
+            let failed = LLVMParseBitcodeInContext2(context, buffer, out);
+            LLVMDisposeMemoryBuffer(buffer);
+            match failed {
+                0 => Ok(()),
+                _ => Err("Failed to parse bitcode".to_string()),
+            }
+        })
+    }
+
+    /// `module`, with the constants of the LLVM module parsed by `parse`.
+    fn with_constants(
+        module: Module,
+        parse: impl FnOnce(LLVMContextRef, *mut LLVMModuleRef) -> Result<(), String>,
+    ) -> Result<Self, String> {
+        let mut ir_module = Self {
+            module,
+            big_endian: false,
+            instructions: HashMap::new(),
+            initializers: HashMap::new(),
+        };
+        unsafe {
+            let context = LLVMContextCreate();
+            let mut llvm_module = ptr::null_mut();
+            let parsed = parse(context, &mut llvm_module);
+            if parsed.is_ok() {
+                ir_module.read_constants(llvm_module);
+                LLVMDisposeModule(llvm_module);
+            }
+            LLVMContextDispose(context);
+            parsed?;
+        }
+        Ok(ir_module)
+    }
+
+    /// Reads the constants of the instructions and global initializers of `module`. Functions
+    /// and globals are in the same order as in `llvm-ir`.
+    unsafe fn read_constants(&mut self, module: LLVMModuleRef) {
+        unsafe {
+            let globals = successors(LLVMGetFirstGlobal(module), |g| LLVMGetNextGlobal(g));
+            let globals: Vec<_> = globals.collect();
+            let layout = LLVMGetModuleDataLayout(module);
+            self.big_endian = matches!(LLVMByteOrder(layout), LLVMByteOrdering::LLVMBigEndian);
+            let reader = ConstantReader {
+                layout,
+                globals: &globals,
+            };
+            for (index, &global) in globals.iter().enumerate() {
+                let initializer = LLVMGetInitializer(global);
+                if !initializer.is_null() {
+                    reader.read_initializer(initializer, &mut vec![], &mut |path, value| {
+                        self.initializers.insert((index, path.to_vec()), value);
+                    });
+                }
+            }
+            let functions = successors(LLVMGetFirstFunction(module), |f| LLVMGetNextFunction(f));
+            let defined = functions.filter(|&f| LLVMIsDeclaration(f) == 0);
+            for (function, f) in defined.enumerate() {
+                let blocks = successors(LLVMGetFirstBasicBlock(f), |b| LLVMGetNextBasicBlock(b));
+                for (block, b) in blocks.enumerate() {
+                    let instrs =
+                        successors(LLVMGetFirstInstruction(b), |i| LLVMGetNextInstruction(i));
+                    for (instruction, i) in instrs.enumerate() {
+                        let mut constants = InstructionConstants::default();
+                        for operand in 0..LLVMGetNumOperands(i) {
+                            reader.read_operand(LLVMGetOperand(i, operand as u32), &mut constants);
+                        }
+                        if !constants.wide.is_empty() || !constants.geps.is_empty() {
+                            self.instructions
+                                .insert((function, block, instruction), constants);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    /// The `Constant::Int` of `bits` bits whose low 64 bits are `value`, used in `scope`. An
+    /// instruction using several wide constants with these low bits is not supported.
+    pub fn int_constant(
+        &self,
+        scope: &ConstantScope,
+        bits: u32,
+        value: u64,
+    ) -> TranslationResult<IntConstant> {
+        if !is_wide(bits) {
+            return Ok(IntConstant::new(value, bits));
+        }
+        let mut candidates = vec![];
+        match scope {
+            ConstantScope::Instruction { .. } => {
+                let constants = self.instruction_constants(scope);
+                for (width, candidate) in constants.into_iter().flat_map(|c| &c.wide) {
+                    if *width == bits
+                        && low_bits(candidate) == value
+                        && !candidates.contains(&candidate)
+                    {
+                        candidates.push(candidate);
+                    }
+                }
+            }
+            ConstantScope::Initializer { global, path } => {
+                let global = self.global_vars.iter().position(|g| &g.name == *global);
+                let key = global.map(|g| (g, path.to_vec()));
+                candidates.extend(key.and_then(|key| self.initializers.get(&key)));
+            }
+        }
+        match candidates[..] {
+            [candidate] => Ok(IntConstant::new(candidate.clone(), bits)),
+            [] => unsupported!(
+                "The value of the i{bits} constant with low {LIMB_BITS} bits {value} cannot be \
+                 recovered from the IR"
+            ),
+            _ => unsupported!(
+                "The i{bits} constants with low {LIMB_BITS} bits {value} of an instruction are \
+                 ambiguous"
+            ),
+        }
+    }
+
+    /// Offset in bytes of the constant `getelementptr` with constant `indices` used in `scope`,
+    /// whose pointer is based on `global`.
+    pub fn gep_offset(
+        &self,
+        scope: &ConstantScope,
+        global: &Name,
+        indices: &[i64],
+    ) -> TranslationResult<i64> {
+        let global = self.global_vars.iter().position(|g| &g.name == global);
+        let mut offsets = vec![];
+        for gep in self
+            .instruction_constants(scope)
+            .into_iter()
+            .flat_map(|c| &c.geps)
+        {
+            if Some(gep.global) == global
+                && gep.indices == indices
+                && !offsets.contains(&gep.offset)
+            {
+                offsets.push(gep.offset);
+            }
+        }
+        match offsets[..] {
+            [offset] => Ok(offset),
+            [] => unsupported!("The offset of a constant getelementptr cannot be recovered"),
+            _ => unsupported!("The constant getelementptrs of an instruction are ambiguous"),
+        }
+    }
+
+    fn instruction_constants(&self, scope: &ConstantScope) -> Option<&InstructionConstants> {
+        let ConstantScope::Instruction {
+            function,
+            block,
+            instruction,
+        } = scope
+        else {
+            return None;
+        };
+        let function = self.functions.iter().position(|f| f.name == *function)?;
+        self.instructions.get(&(function, *block, *instruction))
+    }
+}
+
+impl Deref for IrModule {
+    type Target = Module;
+
+    fn deref(&self) -> &Module {
+        &self.module
+    }
+}
+
+/// Reads constants with the data layout of their module, whose globals are `globals`.
+struct ConstantReader<'g> {
+    layout: LLVMTargetDataRef,
+    globals: &'g [LLVMValueRef],
+}
+
+impl ConstantReader<'_> {
+    /// Adds the wide integers and `getelementptr` expressions of the constant `operand`.
+    unsafe fn read_operand(&self, operand: LLVMValueRef, constants: &mut InstructionConstants) {
+        unsafe {
+            if LLVMIsAConstant(operand).is_null() || !LLVMIsAGlobalValue(operand).is_null() {
+                return;
+            }
+            if let Some(value) = wide_value(operand) {
+                constants.wide.push(value);
+            }
+            if let Some(gep) = self.constant_gep(operand) {
+                constants.geps.push(gep);
+            }
+            for i in 0..LLVMGetNumOperands(operand) {
+                self.read_operand(LLVMGetOperand(operand, i as u32), constants);
+            }
+        }
+    }
+
+    /// Calls `write` with the path and value of the wide integers of `initializer`, at `path`.
+    unsafe fn read_initializer(
+        &self,
+        initializer: LLVMValueRef,
+        path: &mut Vec<usize>,
+        write: &mut impl FnMut(&[usize], BigUint),
+    ) {
+        unsafe {
+            if let Some((_, value)) = wide_value(initializer) {
+                write(path, value);
+            }
+            let aggregate = !LLVMIsAConstantStruct(initializer).is_null()
+                || !LLVMIsAConstantArray(initializer).is_null()
+                || !LLVMIsAConstantVector(initializer).is_null();
+            if aggregate {
+                for i in 0..LLVMGetNumOperands(initializer) {
+                    path.push(i as usize);
+                    self.read_initializer(LLVMGetOperand(initializer, i as u32), path, write);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    /// `constant` as a `getelementptr` with constant indices, based on a global.
+    unsafe fn constant_gep(&self, constant: LLVMValueRef) -> Option<ConstantGep> {
+        unsafe {
+            if LLVMIsAConstantExpr(constant).is_null()
+                || LLVMGetConstOpcode(constant) != LLVMOpcode::LLVMGetElementPtr
+            {
+                return None;
+            }
+            let mut base = LLVMGetOperand(constant, 0);
+            while !LLVMIsAConstantExpr(base).is_null()
+                && LLVMGetConstOpcode(base) == LLVMOpcode::LLVMGetElementPtr
+            {
+                base = LLVMGetOperand(base, 0);
+            }
+            let global = self.globals.iter().position(|&g| g == base)?;
+            let indices = (1..LLVMGetNumOperands(constant))
+                .map(|i| {
+                    let index = LLVMGetOperand(constant, i as u32);
+                    (!LLVMIsAConstantInt(index).is_null()).then(|| LLVMConstIntGetSExtValue(index))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let mut ty = LLVMGetGEPSourceElementType(constant);
+            let (first, rest) = indices.split_first()?;
+            let mut offset = first.wrapping_mul(self.size(ty));
+            for &index in rest {
+                match LLVMGetTypeKind(ty) {
+                    LLVMTypeKind::LLVMStructTypeKind => {
+                        let field = index as u32;
+                        offset =
+                            offset.wrapping_add(LLVMOffsetOfElement(self.layout, ty, field) as i64);
+                        ty = LLVMStructGetTypeAtIndex(ty, field);
+                    }
+                    LLVMTypeKind::LLVMArrayTypeKind | LLVMTypeKind::LLVMVectorTypeKind => {
+                        ty = LLVMGetElementType(ty);
+                        offset = offset.wrapping_add(index.wrapping_mul(self.size(ty)));
+                    }
+                    _ => return None,
+                }
+            }
+            Some(ConstantGep {
+                global,
+                indices,
+                offset,
+            })
+        }
+    }
+
+    unsafe fn size(&self, ty: LLVMTypeRef) -> i64 {
+        unsafe { LLVMABISizeOfType(self.layout, ty) as i64 }
+    }
+}
+
+/// Width and value modulo 2^N of `constant` if it is an integer wider than [`LIMB_BITS`].
+unsafe fn wide_value(constant: LLVMValueRef) -> Option<(u32, BigUint)> {
+    unsafe {
+        if LLVMIsAConstantInt(constant).is_null() {
+            return None;
+        }
+        let bits = LLVMGetIntTypeWidth(LLVMTypeOf(constant));
+        if !is_wide(bits) {
+            return None;
+        }
+        // Printed as `iN <value>`, in decimal.
+        let text = LLVMPrintValueToString(constant);
+        let literal = take_message(text)
+            .rsplit(' ')
+            .next()?
+            .parse::<BigInt>()
+            .ok()?;
+        let modulus = BigInt::from(1) << bits;
+        let value = ((literal % &modulus + &modulus) % &modulus)
+            .magnitude()
+            .clone();
+        Some((bits, value))
+    }
+}
+
+fn low_bits(value: &BigUint) -> u64 {
+    value.iter_u64_digits().next().unwrap_or(0)
+}
+
+/// The string `message` allocated by LLVM, which is then disposed.
+unsafe fn take_message(message: *mut c_char) -> String {
+    if message.is_null() {
+        return String::new();
+    }
+    unsafe {
+        let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+        LLVMDisposeMessage(message);
+        text
+    }
+}
+
+/// `first` and the values following it, until `next` returns null.
+fn successors<T>(first: *mut T, next: impl Fn(*mut T) -> *mut T) -> impl Iterator<Item = *mut T> {
+    std::iter::successors((!first.is_null()).then_some(first), move |&value| {
+        let value = next(value);
+        (!value.is_null()).then_some(value)
+    })
+}
@@ -0,0 +1,321 @@
+//! Translation of LLVM IR functions into circom circuits.
+//!
+//! ```no_run
+//! use analysis::{TranslationOptions, find_function, load_path, translate};
+//!
+//! let module = load_path("contract.ll")?;
+//! let function = find_function(&module, "stylus_contract::SimpleContract::mock_check")?;
+//! let circom = translate(&module, function, "mock_check", &TranslationOptions::default())?;
+//! println!("{}", circom.to_circom());
+//! # Ok::<(), analysis::Error>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use llvm_ir::{Function, function::Parameter, types::TypeRef};
+
+pub mod circom_codegen;
+pub mod instructions;
+pub mod ir_circom;
+pub mod ir_module;
+
+pub use circom_codegen::{CircomCodeGenerator, CircomModule};
+pub use ir_circom::{
+    CallAction, CallPolicy, Diagnostics, TranslationError, TranslationOptions, TranslationReport,
+};
+pub use ir_module::IrModule;
+
+/// Why a module could not be loaded or a function translated.
+#[derive(Debug)]
+pub enum Error {
+    /// The module could not be read or parsed.
+    Load { source: String, message: String },
+    /// No function of the module matches the pattern.
+    FunctionNotFound(String),
+    /// Several functions match the pattern, listed in `matches` by demangled path.
+    AmbiguousFunction {
+        pattern: String,
+        matches: Vec<String>,
+    },
+    /// The function uses constructs that cannot be translated.
+    Translation(Diagnostics),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Load { source, message } => write!(f, "error: Cannot load {source}: {message}"),
+            Error::FunctionNotFound(pattern) => {
+                write!(f, "error: No function matching {pattern} found")
+            }
+            Error::AmbiguousFunction { pattern, matches } => write!(
+                f,
+                "error: More than one function matching {pattern} found: {}",
+                matches.join(", ")
+            ),
+            Error::Translation(diagnostics) => write!(f, "{diagnostics}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Diagnostics> for Error {
+    fn from(diagnostics: Diagnostics) -> Self {
+        Error::Translation(diagnostics)
+    }
+}
+
+/// Loads a module from a textual IR (`.ll`) file.
+pub fn load_ir_path(path: impl AsRef<Path>) -> Result<IrModule, Error> {
+    let path = path.as_ref();
+    let error = |message| Error::Load {
+        source: path.display().to_string(),
+        message,
+    };
+    let text = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    IrModule::from_ir_str(&text).map_err(error)
+}
+
+/// Loads a module from a bitcode (`.bc`) file.
+pub fn load_bc_path(path: impl AsRef<Path>) -> Result<IrModule, Error> {
+    let path = path.as_ref();
+    IrModule::from_bc_path(path).map_err(|message| Error::Load {
+        source: path.display().to_string(),
+        message,
+    })
+}
+
+/// Loads a module from a bitcode file if `path` has the `.bc` extension or starts with the
+/// bitcode magic bytes, from a textual IR file otherwise.
+pub fn load_path(path: impl AsRef<Path>) -> Result<IrModule, Error> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|e| e == "bc") || is_bitcode(path) {
+        load_bc_path(path)
+    } else {
+        load_ir_path(path)
+    }
+}
+
+fn is_bitcode(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| &magic == b"BC\xC0\xDE")
+}
+
+/// Loads a module from textual IR.
+pub fn load_ir_str(ir: &str) -> Result<IrModule, Error> {
+    IrModule::from_ir_str(ir).map_err(|message| Error::Load {
+        source: "IR string".to_string(),
+        message,
+    })
+}
+
+/// Demangled path of a symbol, without the hash of legacy Rust symbols
+/// (`stylus_contract::SimpleContract::mock_check`). Other symbols are returned as they are.
+pub fn demangle(symbol: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(symbol))
+}
+
+/// Functions of `module` selected by `pattern`: the ones whose symbol or demangled path is
+/// exactly `pattern` if any, otherwise the ones where either contains `pattern`.
+pub fn functions_matching<'m>(module: &'m IrModule, pattern: &str) -> Vec<&'m Function> {
+    let exact: Vec<_> = module
+        .functions
+        .iter()
+        .filter(|f| f.name == pattern || demangle(&f.name) == pattern)
+        .collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+    module
+        .functions
+        .iter()
+        .filter(|f| f.name.contains(pattern) || demangle(&f.name).contains(pattern))
+        .collect()
+}
+
+/// The only function of `module` selected by `pattern`, see [`functions_matching`].
+pub fn find_function<'m>(module: &'m IrModule, pattern: &str) -> Result<&'m Function, Error> {
+    match functions_matching(module, pattern).as_slice() {
+        [] => Err(Error::FunctionNotFound(pattern.to_string())),
+        [function] => Ok(function),
+        functions => Err(Error::AmbiguousFunction {
+            pattern: pattern.to_string(),
+            matches: functions.iter().map(|f| demangle(&f.name)).collect(),
+        }),
+    }
+}
+
+/// Name of the main template of `function`: the last segment of its demangled path.
+pub fn main_template_name(function: &Function) -> String {
+    let path = demangle(&function.name);
+    path.rsplit("::")
+        .next()
+        .unwrap_or(&path)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// A function of a module, as listed by [`list_functions`].
+pub struct FunctionSummary {
+    pub symbol: String,
+    pub path: String,
+    /// `path(parameter types) -> return type`.
+    pub signature: String,
+    /// Why the function cannot be translated, `None` when it can.
+    pub error: Option<Error>,
+}
+
+/// Every function of `module`, defined or only declared, with whether it can be translated
+/// with `options`.
+pub fn list_functions(module: &IrModule, options: &TranslationOptions) -> Vec<FunctionSummary> {
+    let defined = module.functions.iter().map(|function| {
+        let error = translate(module, function, main_template_name(function), options).err();
+        summary(
+            &function.name,
+            &function.parameters,
+            &function.return_type,
+            error,
+        )
+    });
+    let declared = module.func_declarations.iter().map(|declaration| {
+        let error = Error::Translation(Diagnostics::from(TranslationError {
+            function: demangle(&declaration.name),
+            block: None,
+            instruction: None,
+            message: "Only declared, the body is not available".to_string(),
+        }));
+        let (parameters, return_type) = (&declaration.parameters, &declaration.return_type);
+        summary(&declaration.name, parameters, return_type, Some(error))
+    });
+    defined.chain(declared).collect()
+}
+
+fn summary(
+    symbol: &str,
+    parameters: &[Parameter],
+    return_type: &TypeRef,
+    error: Option<Error>,
+) -> FunctionSummary {
+    let path = demangle(symbol);
+    let parameters: Vec<_> = parameters.iter().map(|p| p.ty.to_string()).collect();
+    FunctionSummary {
+        symbol: symbol.to_string(),
+        signature: format!("{path}({}) -> {return_type}", parameters.join(", ")),
+        path,
+        error,
+    }
+}
+
+/// A translated function, with the calls the call policy left out of it.
+pub struct Translation {
+    pub circom: CircomModule,
+    pub report: TranslationReport,
+}
+
+impl Translation {
+    pub fn to_circom(&self) -> String {
+        self.circom.to_circom()
+    }
+}
+
+/// Translates `function` (and the functions it calls) into a circom module whose main
+/// component is the template `main`.
+pub fn translate(
+    module: &IrModule,
+    function: &Function,
+    main: impl Into<String>,
+    options: &TranslationOptions,
+) -> Result<Translation, Error> {
+    let main = main.into();
+    let (templates, report) = ir_circom::ir_to_circom(main.clone(), function, module, options)?;
+    let mut components = known_components();
+    for (component, include) in options.call_policy.components() {
+        components.insert(component.to_string(), include.to_string());
+    }
+    Ok(Translation {
+        circom: CircomModule::new(templates, components, main),
+        report,
+    })
+}
+
+/// Translates `function` and renders the circom code.
+pub fn translate_to_string(
+    module: &IrModule,
+    function: &Function,
+    main: impl Into<String>,
+    options: &TranslationOptions,
+) -> Result<String, Error> {
+    translate(module, function, main, options).map(|translation| translation.to_circom())
+}
+
+/// Templates of the `circuit` directory the generated code uses, with their include paths.
+pub fn known_components() -> HashMap<String, String> {
+    [
+        ("IsEqual", "./circomlib/comparators.circom"),
+        ("LessThan", "./circomlib/comparators.circom"),
+        ("LessEqThan", "./circomlib/comparators.circom"),
+        ("GreaterThan", "./circomlib/comparators.circom"),
+        ("GreaterEqThan", "./circomlib/comparators.circom"),
+        ("Num2Bits", "./circomlib/bitify.circom"),
+        ("SignedLessThan", "./llvm/comparators.circom"),
+        ("SignedLessEqThan", "./llvm/comparators.circom"),
+        ("SignedGreaterThan", "./llvm/comparators.circom"),
+        ("SignedGreaterEqThan", "./llvm/comparators.circom"),
+        ("IntAdd", "./llvm/arithmetic.circom"),
+        ("IntSub", "./llvm/arithmetic.circom"),
+        ("IntMul", "./llvm/arithmetic.circom"),
+        ("UDivRem", "./llvm/arithmetic.circom"),
+        ("SDivRem", "./llvm/arithmetic.circom"),
+        ("AddWithOverflow", "./llvm/arithmetic.circom"),
+        ("SubWithOverflow", "./llvm/arithmetic.circom"),
+        ("MulWithOverflow", "./llvm/arithmetic.circom"),
+        ("IntAnd", "./llvm/bitwise.circom"),
+        ("IntOr", "./llvm/bitwise.circom"),
+        ("IntXor", "./llvm/bitwise.circom"),
+        ("Shl", "./llvm/bitwise.circom"),
+        ("LShr", "./llvm/bitwise.circom"),
+        ("AShr", "./llvm/bitwise.circom"),
+        ("ShlConst", "./llvm/bitwise.circom"),
+        ("LShrConst", "./llvm/bitwise.circom"),
+        ("AShrConst", "./llvm/bitwise.circom"),
+        ("UMin", "./llvm/intrinsics.circom"),
+        ("UMax", "./llvm/intrinsics.circom"),
+        ("SMin", "./llvm/intrinsics.circom"),
+        ("SMax", "./llvm/intrinsics.circom"),
+        ("Abs", "./llvm/intrinsics.circom"),
+        ("Ctpop", "./llvm/intrinsics.circom"),
+        ("Ctlz", "./llvm/intrinsics.circom"),
+        ("Cttz", "./llvm/intrinsics.circom"),
+        ("Bswap", "./llvm/intrinsics.circom"),
+        ("FunnelShift", "./llvm/intrinsics.circom"),
+        ("Trunc", "./llvm/conversions.circom"),
+        ("SignExtend", "./llvm/conversions.circom"),
+        ("IndexSelector", "./llvm/memory.circom"),
+        ("IndexDecoder", "./llvm/memory.circom"),
+        ("WideAdd", "./llvm/wide.circom"),
+        ("WideSub", "./llvm/wide.circom"),
+        ("WideMul", "./llvm/wide.circom"),
+        ("WideAddWithOverflow", "./llvm/wide.circom"),
+        ("WideSubWithOverflow", "./llvm/wide.circom"),
+        ("WideMulWithOverflow", "./llvm/wide.circom"),
+        ("WideMin", "./llvm/wide.circom"),
+        ("WideMax", "./llvm/wide.circom"),
+        ("WideIsEqual", "./llvm/wide.circom"),
+        ("WideLessThan", "./llvm/wide.circom"),
+        ("WideShift", "./llvm/wide.circom"),
+        ("WideSignExtend", "./llvm/wide.circom"),
+    ]
+    .into_iter()
+    .map(|(n, i)| (n.to_string(), i.to_string()))
+    .collect()
+}
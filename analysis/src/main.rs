@@ -1,65 +1,145 @@
-use clap::Parser;
-use llvm_ir::Module;
-use std::{collections::HashMap, fs, path::Path};
-
-use crate::{
-    circom_codegen::{CircomCodeGenerator, CircomModule},
-    ir_circom::ir_to_circom,
+use analysis::{
+    CallPolicy, IrModule, TranslationOptions, demangle, find_function, list_functions, load_path,
+    main_template_name, translate,
 };
-
-pub mod circom_codegen;
-pub mod instructions;
-pub mod ir_circom;
+use clap::Parser;
+use std::{fmt::Display, fs, process};
 
 #[derive(clap::Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short, long, value_name = "FILE")]
-    ir: String,
-    #[arg(short, long)]
-    function: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    input: Input,
+    /// Function to translate: its demangled path (`stylus_contract::SimpleContract::mock_check`),
+    /// its symbol, or a part of either matching a single function
+    #[arg(short, long, required = true)]
+    function: Option<String>,
     #[arg(short, long, value_name = "FILE")]
     output: Option<String>,
 }
 
-fn main() -> () {
-    let args = Args::parse();
+#[derive(clap::Subcommand)]
+enum Command {
+    /// List every function of the module with its signature and whether it can be translated
+    List {
+        #[command(flatten)]
+        input: Input,
+    },
+}
 
-    let path = Path::new(&args.ir);
-    let module = Module::from_ir_path(path).unwrap();
+#[derive(clap::Args)]
+struct Input {
+    /// LLVM module, as textual IR (`.ll`) or bitcode (`.bc`)
+    #[arg(short, long, value_name = "FILE", required = true)]
+    ir: Option<String>,
+    /// Unrolling bound of loops whose trip count cannot be inferred, either for every
+    /// function (`N`) or for the functions matching a name (`NAME=N`)
+    #[arg(long, value_name = "[NAME=]N", value_parser = parse_loop_bound)]
+    loop_bound: Vec<(Option<String>, usize)>,
+    /// Rules mapping callees to the way their calls are translated, one `PATTERN = ACTION`
+    /// per line, on top of the default ones
+    #[arg(long, value_name = "FILE")]
+    call_policy: Option<String>,
+}
 
-    let functions = module
-        .functions
-        .iter()
-        .filter(|f| f.name.contains(&args.function))
-        .collect::<Vec<_>>();
+impl Input {
+    fn load(self) -> (IrModule, TranslationOptions) {
+        let Some(ir) = self.ir else {
+            fail("error: --ir is required")
+        };
+        let module = load_path(&ir).unwrap_or_else(|e| fail(e));
 
-    if functions.is_empty() {
-        panic!("No function matching {} found!", args.function);
+        let mut options = TranslationOptions::default();
+        for (name, bound) in self.loop_bound {
+            match name {
+                Some(name) => options.loop_bounds.push((name, bound)),
+                None => options.default_loop_bound = Some(bound),
+            }
+        }
+
+        if let Some(path) = &self.call_policy {
+            let config = fs::read_to_string(path)
+                .unwrap_or_else(|e| fail(format!("error: Cannot read {path}: {e}")));
+            options.call_policy = CallPolicy::default()
+                .with_config(&config)
+                .unwrap_or_else(|e| fail(format!("error: Invalid call policy {path}: {e}")));
+        }
+        (module, options)
     }
-    if functions.len() > 1 {
-        panic!(
-            "More than one function matching {} found! \n {:?}",
-            args.function, functions
-        );
+}
+
+fn parse_loop_bound(s: &str) -> Result<(Option<String>, usize), String> {
+    let (name, bound) = match s.rsplit_once('=') {
+        Some((name, bound)) => (Some(name.to_string()), bound),
+        None => (None, s),
+    };
+    let bound = bound
+        .parse()
+        .map_err(|e| format!("invalid loop bound {bound}: {e}"))?;
+    Ok((name, bound))
+}
+
+/// Prints `error` and exits with a failure status.
+fn fail(error: impl Display) -> ! {
+    eprintln!("{error}");
+    process::exit(1)
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Some(Command::List { input }) = args.command {
+        let (module, options) = input.load();
+        list(&module, &options);
+        return;
     }
 
-    let module = CircomModule::new(
-        vec![ir_to_circom(args.function.clone(), functions[0])],
-        known_components(),
-        args.function,
-    );
+    let (module, options) = args.input.load();
+    let Some(pattern) = args.function else {
+        fail("error: --function is required")
+    };
+    let function = find_function(&module, &pattern).unwrap_or_else(|e| fail(e));
 
-    let circom = module.to_circom();
+    let translation = translate(&module, function, main_template_name(function), &options)
+        .unwrap_or_else(|e| fail(e));
+    for (function, call) in &translation.report.ignored_calls {
+        eprintln!("Ignored call in {}: {call}", demangle(function));
+    }
+
+    let circom = translation.to_circom();
 
     match args.output {
         None => println!("{circom}"),
-        Some(output) => fs::write(output, circom).unwrap(),
+        Some(output) => fs::write(&output, circom)
+            .unwrap_or_else(|e| fail(format!("error: Cannot write {output}: {e}"))),
     }
 }
 
-fn known_components() -> HashMap<String, String> {
-    [("IsEqual", "./circomlib/comparators.circom")]
-        .into_iter()
-        .map(|(n, i)| (n.to_string(), i.to_string()))
-        .collect()
+/// Prints one line per function: whether it can be translated, its signature and its symbol,
+/// followed by the first reason it cannot be translated.
+fn list(module: &IrModule, options: &TranslationOptions) {
+    for function in list_functions(module, options) {
+        let status = if function.error.is_none() {
+            "ok"
+        } else {
+            "unsupported"
+        };
+        println!("{status:<12}{}  [{}]", function.signature, function.symbol);
+        match function.error {
+            Some(analysis::Error::Translation(diagnostics)) => {
+                let count = diagnostics.errors().len();
+                let first = &diagnostics.errors()[0].message;
+                let more = if count > 1 {
+                    format!(" (and {} more)", count - 1)
+                } else {
+                    String::new()
+                };
+                println!("            {first}{more}");
+            }
+            Some(error) => println!("            {error}"),
+            None => {}
+        }
+    }
 }
@@ -0,0 +1,609 @@
+//! Translation of small IR snippets through the library interface.
+
+use analysis::{
+    CircomModule, Error, TranslationError, TranslationOptions, find_function,
+    instructions::{
+        BinaryOperationType, CircomInstr, CircomOperand, ConditionalValue, Expression, Reference,
+        SignalDeclaration, Template,
+    },
+    list_functions, load_ir_str, translate_to_string,
+};
+
+fn translate_with(ir: &str, function: &str, options: &TranslationOptions) -> Result<String, Error> {
+    let module = load_ir_str(ir).expect("the IR should parse");
+    let function = find_function(&module, function)?;
+    translate_to_string(&module, function, "main", options)
+}
+
+fn translate(ir: &str, function: &str) -> Result<String, Error> {
+    translate_with(ir, function, &TranslationOptions::default())
+}
+
+fn translate_module(ir: &str, function: &str) -> CircomModule {
+    let module = load_ir_str(ir).expect("the IR should parse");
+    let function = find_function(&module, function).unwrap();
+    let options = TranslationOptions::default();
+    analysis::translate(&module, function, "main", &options)
+        .unwrap()
+        .circom
+}
+
+fn signal(name: &str) -> Reference {
+    Reference::SignalRef(name.to_string())
+}
+
+/// Right-hand side of the assignment of `left` in `template`.
+fn assigned<'t>(template: &'t Template, left: &Reference) -> &'t Expression {
+    let mut assignments = template.instructions.iter().filter_map(|i| match i {
+        CircomInstr::ConstraintGenerationAssigment(a) if &a.left == left => Some(&a.right),
+        _ => None,
+    });
+    assignments.next().expect("the signal should be assigned")
+}
+
+/// Signal referenced by the expression `expression`.
+fn referenced(expression: &Expression) -> &Reference {
+    match expression {
+        Expression::Operand(CircomOperand::Reference(reference)) => reference,
+        other => panic!("expected a signal, got {other:?}"),
+    }
+}
+
+/// Errors of a translation expected to fail.
+fn errors(result: Result<String, Error>) -> Vec<TranslationError> {
+    match result {
+        Err(Error::Translation(diagnostics)) => diagnostics.errors().to_vec(),
+        Err(other) => panic!("expected translation errors, got {other}"),
+        Ok(circom) => panic!("expected translation errors, got\n{circom}"),
+    }
+}
+
+const BRANCH: &str = r#"
+define i32 @branch(i32 %x, i1 %c) {
+start:
+  br i1 %c, label %then, label %else
+
+then:
+  %a = add i32 %x, 1
+  br label %join
+
+else:
+  %b = mul i32 %x, 3
+  br label %join
+
+join:
+  %r = phi i32 [ %a, %then ], [ %b, %else ]
+  ret i32 %r
+}
+
+define i32 @constant_branch(i32 %x) {
+start:
+  br i1 false, label %then, label %else
+
+then:
+  %a = add i32 %x, 1
+  br label %join
+
+else:
+  %b = mul i32 %x, 3
+  br label %join
+
+join:
+  %r = phi i32 [ %a, %then ], [ %b, %else ]
+  ret i32 %r
+}
+"#;
+
+fn enabled(component: &str) -> Reference {
+    Reference::ComponentField {
+        component: component.to_string(),
+        field: "enabled".to_string(),
+    }
+}
+
+#[test]
+fn branches_merge_with_a_mux() {
+    let circom = translate_module(BRANCH, "branch");
+    assert_eq!(circom.main(), "main");
+    let [template] = circom.templates() else {
+        panic!("expected a single template");
+    };
+    assert_eq!(template.name, "main");
+    let instructions = &template.instructions;
+    for declaration in [
+        SignalDeclaration::Input("x".to_string()),
+        SignalDeclaration::Input("c".to_string()),
+        SignalDeclaration::Output("OUTPUT_".to_string()),
+    ] {
+        assert!(instructions.contains(&CircomInstr::SignalDeclaration(declaration)));
+    }
+    let components: Vec<_> = (instructions.iter())
+        .filter_map(|i| match i {
+            CircomInstr::ComponentInstatiation(c) => Some((c.name.as_str(), c.component.as_str())),
+            _ => None,
+        })
+        .collect();
+    assert!(components.contains(&("a_ADD", "IntAdd")));
+    assert!(components.contains(&("b_MUL", "IntMul")));
+
+    // Each branch is enabled by its own edge, the `else` one by the negated condition.
+    assert_eq!(
+        referenced(assigned(template, &enabled("a_ADD"))),
+        &signal("c")
+    );
+    let negated = referenced(assigned(template, &enabled("b_MUL")));
+    let Expression::BinaryOperation(negation) = assigned(template, negated) else {
+        panic!("expected the negation of the condition");
+    };
+    assert!(negation.left.is_constant(1));
+    assert_eq!(negation.op, BinaryOperationType::Sub);
+    assert_eq!(negation.right, CircomOperand::Reference(signal("c")));
+
+    // The phi selects the value of the branch taken, and is what the function returns.
+    let phi = Expression::Conditional(ConditionalValue {
+        cond: CircomOperand::Reference(signal("c")),
+        v_if_true: CircomOperand::Reference(signal("a")),
+        v_if_false: CircomOperand::Reference(signal("b")),
+    });
+    assert_eq!(assigned(template, &signal("r_PHI0")), &phi);
+    assert_eq!(
+        referenced(assigned(template, &signal("r"))),
+        &signal("r_PHI0")
+    );
+}
+
+#[test]
+fn constant_branches_are_folded() {
+    let circom = translate_module(BRANCH, "constant_branch");
+    let template = &circom.templates()[0];
+    let constant = |expression: &Expression| match expression {
+        Expression::Operand(CircomOperand::Constant(c)) => c.to_u64(),
+        _ => None,
+    };
+    assert_eq!(constant(assigned(template, &enabled("a_ADD"))), Some(0));
+    assert_eq!(constant(assigned(template, &enabled("b_MUL"))), Some(1));
+    // No signal is declared for the negation of the constant condition.
+    let declared: Vec<_> = (template.instructions.iter())
+        .filter_map(|i| match i {
+            CircomInstr::SignalDeclaration(SignalDeclaration::Private(name)) => Some(name),
+            _ => None,
+        })
+        .collect();
+    assert!(declared.iter().all(|name| !name.starts_with(['0', '1'])));
+}
+
+const LOOPS: &str = r#"
+define i32 @sum(i32 %x) {
+entry:
+  br label %loop
+
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ]
+  %acc = phi i32 [ 0, %entry ], [ %acc.next, %loop ]
+  %acc.next = add i32 %acc, %x
+  %i.next = add i32 %i, 1
+  %done = icmp eq i32 %i.next, 3
+  br i1 %done, label %exit, label %loop
+
+exit:
+  %result = phi i32 [ %acc.next, %loop ]
+  ret i32 %result
+}
+
+define i32 @count(i32 %n) {
+entry:
+  br label %loop
+
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ]
+  %i.next = add i32 %i, 1
+  %done = icmp uge i32 %i.next, %n
+  br i1 %done, label %exit, label %loop
+
+exit:
+  %result = phi i32 [ %i.next, %loop ]
+  ret i32 %result
+}
+"#;
+
+fn loop_bounds(bounds: &[(&str, usize)]) -> TranslationOptions {
+    TranslationOptions {
+        loop_bounds: bounds.iter().map(|(p, b)| (p.to_string(), *b)).collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn loops_are_unrolled_by_their_inferred_trip_count() {
+    let circom = translate(LOOPS, "sum").unwrap();
+    for i in 0..3 {
+        assert!(circom.contains(&format!("component acc_next_it{i}_ADD = IntAdd(")));
+    }
+    assert!(!circom.contains("acc_next_it3"));
+}
+
+#[test]
+fn loops_without_a_trip_count_need_a_bound() {
+    let errors = errors(translate(LOOPS, "count"));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].function, "count");
+    assert!(errors[0].message.contains("Cannot infer the trip count"));
+
+    let default = TranslationOptions {
+        default_loop_bound: Some(2),
+        ..Default::default()
+    };
+    let circom = translate_with(LOOPS, "count", &default).unwrap();
+    assert!(circom.contains("i_next_it1_ADD"));
+    assert!(!circom.contains("i_next_it2"));
+}
+
+#[test]
+fn loop_bounds_prefer_exact_names() {
+    let circom = translate_with(LOOPS, "count", &loop_bounds(&[("co", 2), ("count", 3)])).unwrap();
+    assert!(circom.contains("i_next_it2_ADD"));
+    assert!(!circom.contains("i_next_it3"));
+
+    let circom = translate_with(LOOPS, "count", &loop_bounds(&[("cou", 2), ("cou", 3)])).unwrap();
+    assert!(circom.contains("i_next_it1_ADD"));
+    assert!(!circom.contains("i_next_it2"));
+}
+
+#[test]
+fn ambiguous_loop_bounds_are_reported() {
+    let options = loop_bounds(&[("co", 2), ("unt", 3)]);
+    let errors = errors(translate_with(LOOPS, "count", &options));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("Ambiguous loop bound of count"));
+    assert!(errors[0].message.contains("co, unt"));
+}
+
+const MEMORY: &str = r#"
+@TABLE = private unnamed_addr constant [4 x i8] c"\01\02\04\08", align 1
+
+define i32 @memory(i32 %x, i64 %i) {
+start:
+  %buf = alloca [4 x i32], align 4
+  %p = getelementptr inbounds [4 x i32], ptr %buf, i64 0, i64 2
+  store i32 7, ptr %p, align 4
+  %q = getelementptr inbounds [4 x i32], ptr %buf, i64 0, i64 %i
+  store i32 %x, ptr %q, align 4
+  %v = load i32, ptr %p, align 4
+  ret i32 %v
+}
+
+define i32 @input(ptr align 4 dereferenceable(16) %data, i64 %i) {
+start:
+  %p = getelementptr inbounds i32, ptr %data, i64 %i
+  %v = load i32, ptr %p, align 4
+  ret i32 %v
+}
+
+define i8 @lookup(i64 %i) {
+start:
+  %p = getelementptr inbounds [4 x i8], ptr @TABLE, i64 0, i64 %i
+  %v = load i8, ptr %p, align 1
+  %w = load i8, ptr getelementptr inbounds ([4 x i8], ptr @TABLE, i64 0, i64 2), align 1
+  %r = add i8 %v, %w
+  ret i8 %r
+}
+
+define i32 @copy(i32 %x) {
+start:
+  %a = alloca [2 x i32], align 4
+  %b = alloca [2 x i32], align 4
+  store i32 %x, ptr %a, align 4
+  %a1 = getelementptr inbounds i8, ptr %a, i64 4
+  store i32 5, ptr %a1, align 4
+  call void @llvm.memcpy.p0.p0.i64(ptr align 4 %b, ptr align 4 %a, i64 8, i1 false)
+  %b1 = getelementptr inbounds i8, ptr %b, i64 4
+  %v = load i32, ptr %b1, align 4
+  ret i32 %v
+}
+
+define i32 @dynamic_length(i32 %x, i64 %n) {
+start:
+  %a = alloca [2 x i32], align 4
+  %b = alloca [2 x i32], align 4
+  store i32 %x, ptr %a, align 4
+  call void @llvm.memcpy.p0.p0.i64(ptr align 4 %b, ptr align 4 %a, i64 %n, i1 false)
+  %v = load i32, ptr %b, align 4
+  ret i32 %v
+}
+
+declare void @llvm.memcpy.p0.p0.i64(ptr noalias nocapture writeonly, ptr noalias nocapture readonly, i64, i1 immarg)
+"#;
+
+#[test]
+fn stores_through_dynamic_indices_are_decoded() {
+    let circom = translate(MEMORY, "memory").unwrap();
+    assert!(circom.contains("buf_o8_m0"));
+    assert!(circom.contains("component STORE_0_DEC = IndexDecoder(4);"));
+    assert!(circom.contains("include \"./llvm/memory.circom\";"));
+}
+
+#[test]
+fn sized_pointer_parameters_are_input_arrays() {
+    let circom = translate(MEMORY, "input").unwrap();
+    assert!(circom.contains("signal input data[4];"));
+    assert!(circom.contains("component v_SEL = IndexSelector(4);"));
+    assert!(circom.contains("v_SEL.in[3] <== data_o12_m0;"));
+}
+
+#[test]
+fn constant_globals_are_read_at_compile_time() {
+    let circom = translate(MEMORY, "lookup").unwrap();
+    assert!(circom.contains("component v_SEL = IndexSelector(4);"));
+    assert!(circom.contains("v_SEL.in[0] <== 1;"));
+    assert!(circom.contains("v_SEL.in[3] <== 8;"));
+    assert!(circom.contains("w <== 4;"));
+}
+
+const GLOBALS: &str = r#"
+target datalayout = "e-m:e-p:32:32-p10:8:8-p20:8:8-i64:64-i128:128-n32:64-S128-ni:1:10:20"
+
+@PAIRS = constant [2 x { i32, i128 }] [{ i32, i128 } { i32 1, i128 2 }, { i32, i128 } { i32 3, i128 18446744073709551617 }]
+
+define i128 @fields() {
+start:
+  %a = load i32, ptr getelementptr inbounds ([2 x { i32, i128 }], ptr @PAIRS, i32 0, i32 1, i32 0), align 16
+  %b = load i128, ptr getelementptr inbounds (i8, ptr @PAIRS, i32 48), align 16
+  %a128 = zext i32 %a to i128
+  %r = add i128 %a128, %b
+  ret i128 %r
+}
+"#;
+
+#[test]
+fn constant_getelementptrs_are_offset_by_their_source_type() {
+    let circom = translate(GLOBALS, "fields").unwrap();
+    assert!(circom.contains("a <== 3;"));
+    // The byte offset of the second `i128`, as rustc emits it, and its full value.
+    assert!(circom.contains("b_L0 <== 1;"));
+    assert!(circom.contains("b_L1 <== 1;"));
+}
+
+#[test]
+fn memcpy_copies_slot_by_slot() {
+    let circom = translate(MEMORY, "copy").unwrap();
+    assert!(circom.contains("b_o4_m1"));
+    assert!(!circom.contains("llvm.memcpy"));
+
+    let errors = errors(translate(MEMORY, "dynamic_length"));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("dynamic length"));
+}
+
+const WIDE: &str = r#"
+define i128 @wide(i128 %x, i128 %y) {
+start:
+  %s = add i128 %x, %y
+  %m = mul i128 %s, -1
+  %r = call i128 @llvm.umin.i128(i128 %m, i128 %x)
+  ret i128 %r
+}
+
+define i128 @checked(i128 %x, i128 %y) {
+start:
+  %res = call { i128, i1 } @llvm.uadd.with.overflow.i128(i128 %x, i128 %y)
+  %sum = extractvalue { i128, i1 } %res, 0
+  %overflow = extractvalue { i128, i1 } %res, 1
+  %r = select i1 %overflow, i128 0, i128 %sum
+  ret i128 %r
+}
+
+define i128 @double(i128 %x) {
+start:
+  %d = add i128 %x, %x
+  ret i128 %d
+}
+
+define i128 @caller(i128 %x) {
+start:
+  %r = call i128 @double(i128 %x)
+  ret i128 %r
+}
+
+define i128 @flagged(i128 %x, i128 %y) {
+start:
+  %m = mul nsw i128 %x, %y
+  ret i128 %m
+}
+
+define i128 @shared_low_bits(i128 %x) {
+start:
+  %a = add i128 %x, 1
+  %b = add i128 %a, 92233720368547758081
+  ret i128 %b
+}
+
+define i128 @unrelated(i128 %x) {
+start:
+  %p = alloca i128, align 16
+  store i128 36893488147419103233, ptr %p, align 16
+  %v = load i128, ptr %p, align 16
+  %r = add i128 %v, 16
+  ret i128 %r
+}
+
+define i128 @ambiguous(i1 %c) {
+start:
+  %r = select i1 %c, i128 1, i128 18446744073709551617
+  ret i128 %r
+}
+
+declare i128 @llvm.umin.i128(i128, i128)
+declare { i128, i1 } @llvm.uadd.with.overflow.i128(i128, i128)
+"#;
+
+#[test]
+fn wide_integers_are_split_into_limbs() {
+    let circom = translate(WIDE, "wide").unwrap();
+    assert!(circom.contains("signal input x_L0;"));
+    assert!(circom.contains("signal input x_L1;"));
+    assert!(circom.contains("signal output OUTPUT__L1;"));
+    assert!(circom.contains("= WideAdd("));
+    assert!(circom.contains("= WideMul("));
+    assert!(circom.contains("= WideMin("));
+    // The full value of `-1`, not only its low 64 bits.
+    assert!(circom.matches("18446744073709551615").count() >= 2);
+    assert!(circom.contains("include \"./llvm/wide.circom\";"));
+}
+
+#[test]
+fn wide_checked_arithmetic_has_its_own_template() {
+    let circom = translate(WIDE, "checked").unwrap();
+    assert!(circom.contains("= WideAddWithOverflow("));
+}
+
+#[test]
+fn wide_arguments_are_passed_limb_by_limb() {
+    let circom = translate(WIDE, "caller").unwrap();
+    assert_eq!(circom.matches("template ").count(), 2);
+    assert!(circom.contains(".x_L1 <== x_L1;"));
+    assert!(circom.contains(".OUTPUT__L1;"));
+}
+
+#[test]
+fn unchecked_wide_flags_are_rejected() {
+    let errors = errors(translate(WIDE, "flagged"));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("`mul nsw`"));
+}
+
+#[test]
+fn wide_constants_are_read_from_their_instruction() {
+    // 1 and 5 * 2^64 + 1, whose low 64 bits are also those of a constant of `unrelated`.
+    let circom = translate(WIDE, "shared_low_bits").unwrap();
+    assert!(circom.contains("a_ADD.in[1][1] <== 0;"));
+    assert!(circom.contains("b_ADD.in[1][1] <== 5;"));
+}
+
+#[test]
+fn alignments_are_not_taken_for_wide_constants() {
+    let circom = translate(WIDE, "unrelated").unwrap();
+    assert!(circom.contains("r_ADD.in[1][0] <== 16;"));
+    assert!(circom.contains("r_ADD.in[1][1] <== 0;"));
+}
+
+#[test]
+fn ambiguous_wide_constants_are_rejected() {
+    let errors = errors(translate(WIDE, "ambiguous"));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("are ambiguous"));
+}
+
+const WIDE_SWITCH: &str = r#"
+define i128 @wide_switch(i128 %x) {
+start:
+  switch i128 %x, label %other [
+    i128 18446744073709551617, label %one
+  ]
+
+one:
+  ret i128 1
+
+other:
+  ret i128 0
+}
+"#;
+
+#[test]
+fn wide_switches_are_rejected() {
+    let errors = errors(translate(WIDE_SWITCH, "wide_switch"));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("`switch` on an i128"));
+}
+
+const UNSUPPORTED: &str = r#"
+define i128 @unsupported(i128 %x, i128 %y, i32 %z) {
+start:
+  %q = udiv i128 %x, %y
+  %r = add i128 %q, 1
+  %b = call i32 @llvm.bitreverse.i32(i32 %z)
+  %c = add i32 %b, 1
+  ret i128 %r
+}
+
+declare i32 @llvm.bitreverse.i32(i32)
+"#;
+
+#[test]
+fn every_unsupported_construct_is_reported() {
+    let errors = errors(translate(UNSUPPORTED, "unsupported"));
+    // The instructions using the failed values are skipped rather than reported.
+    assert_eq!(errors.len(), 2, "{errors:?}");
+    assert!(
+        errors[0]
+            .message
+            .starts_with("Division of integers wider than 64 bits")
+    );
+    assert!(
+        errors[1]
+            .message
+            .starts_with("Intrinsic llvm.bitreverse.i32 has no lowering")
+    );
+    assert!(errors[1].message.ends_with("llvm.memset.*"));
+    for error in &errors {
+        assert_eq!(error.function, "unsupported");
+        assert_eq!(error.block.as_deref(), Some("%start"));
+        assert!(error.instruction.is_some());
+    }
+}
+
+const MANGLED: &str = r#"
+define i32 @_ZN8contract5Check6verify17h0123456789abcdefE(i32 %x) {
+start:
+  ret i32 %x
+}
+
+define i32 @_ZN8contract5Check12verify_inner17hfedcba9876543210E(i32 %x) {
+start:
+  ret i32 %x
+}
+"#;
+
+#[test]
+fn functions_are_found_by_demangled_path() {
+    let module = load_ir_str(MANGLED).unwrap();
+    let verify = find_function(&module, "contract::Check::verify").unwrap();
+    assert_eq!(verify.name, "_ZN8contract5Check6verify17h0123456789abcdefE");
+    let inner = find_function(&module, "verify_inner").unwrap();
+    assert_eq!(
+        inner.name,
+        "_ZN8contract5Check12verify_inner17hfedcba9876543210E"
+    );
+    let symbol = find_function(&module, "_ZN8contract5Check6verify17h0123456789abcdefE").unwrap();
+    assert_eq!(symbol.name, verify.name);
+
+    let paths: Vec<_> = list_functions(&module, &TranslationOptions::default())
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+    assert_eq!(
+        paths,
+        ["contract::Check::verify", "contract::Check::verify_inner"]
+    );
+}
+
+#[test]
+fn ambiguous_function_patterns_are_reported() {
+    let module = load_ir_str(MANGLED).unwrap();
+    match find_function(&module, "Check::verify") {
+        Err(Error::AmbiguousFunction { pattern, matches }) => {
+            assert_eq!(pattern, "Check::verify");
+            assert_eq!(
+                matches,
+                ["contract::Check::verify", "contract::Check::verify_inner"]
+            );
+        }
+        other => panic!(
+            "expected an ambiguous pattern, got {:?}",
+            other.map(|f| &f.name)
+        ),
+    }
+    assert!(matches!(
+        find_function(&module, "missing"),
+        Err(Error::FunctionNotFound(_))
+    ));
+}
@@ -0,0 +1,319 @@
+pragma circom 2.0.0;
+
+include "../circomlib/bitify.circom";
+include "../circomlib/comparators.circom";
+
+// Signed value of an n-bit two's complement integer, as a field element.
+template SignedValue(n) {
+    signal input in;
+    signal output out;
+
+    component n2b = Num2Bits(n);
+    n2b.in <== in;
+
+    out <== in - n2b.out[n-1] * 2**n;
+}
+
+// Constrains a signed field value to lie in [-2^(n-1), 2^(n-1)) when enabled.
+template SignedFitsIfEnabled(n) {
+    signal input enabled;
+    signal input in;
+
+    component n2b = Num2Bits(n);
+    n2b.in <== (in + 2**(n-1)) * enabled;
+}
+
+// All the templates below take n-bit operands and produce the n-bit result
+// reduced modulo 2^n. When the instruction carries the `nuw`/`nsw` flags the
+// wrapping is forbidden instead, but only on the executed path (`enabled`).
+
+template IntAdd(n, nuw, nsw) {
+    assert(n <= 252);
+    signal input in[2];
+    signal input enabled;
+    signal output out;
+
+    signal carry;
+    carry <-- (in[0] + in[1]) >> n;
+    carry * (carry - 1) === 0;
+    out <== in[0] + in[1] - carry * 2**n;
+
+    component range = Num2Bits(n);
+    range.in <== out;
+
+    component s[2];
+    component fits;
+    if (nuw == 1) {
+        carry * enabled === 0;
+    }
+    if (nsw == 1) {
+        s[0] = SignedValue(n);
+        s[1] = SignedValue(n);
+        s[0].in <== in[0];
+        s[1].in <== in[1];
+        fits = SignedFitsIfEnabled(n);
+        fits.enabled <== enabled;
+        fits.in <== s[0].out + s[1].out;
+    }
+}
+
+template IntSub(n, nuw, nsw) {
+    assert(n <= 252);
+    signal input in[2];
+    signal input enabled;
+    signal output out;
+
+    signal borrow;
+    borrow <-- in[0] < in[1] ? 1 : 0;
+    borrow * (borrow - 1) === 0;
+    out <== in[0] - in[1] + borrow * 2**n;
+
+    component range = Num2Bits(n);
+    range.in <== out;
+
+    component s[2];
+    component fits;
+    if (nuw == 1) {
+        borrow * enabled === 0;
+    }
+    if (nsw == 1) {
+        s[0] = SignedValue(n);
+        s[1] = SignedValue(n);
+        s[0].in <== in[0];
+        s[1].in <== in[1];
+        fits = SignedFitsIfEnabled(n);
+        fits.enabled <== enabled;
+        fits.in <== s[0].out - s[1].out;
+    }
+}
+
+template IntMul(n, nuw, nsw) {
+    // The full product has to fit in the field.
+    assert(n <= 126);
+    signal input in[2];
+    signal input enabled;
+    signal output out;
+
+    signal quotient;
+    quotient <-- (in[0] * in[1]) \ 2**n;
+    out <-- (in[0] * in[1]) % 2**n;
+    in[0] * in[1] === quotient * 2**n + out;
+
+    component range = Num2Bits(n);
+    range.in <== out;
+    component quotientRange = Num2Bits(n);
+    quotientRange.in <== quotient;
+
+    component s[2];
+    component fits;
+    if (nuw == 1) {
+        quotient * enabled === 0;
+    }
+    if (nsw == 1) {
+        s[0] = SignedValue(n);
+        s[1] = SignedValue(n);
+        s[0].in <== in[0];
+        s[1].in <== in[1];
+        fits = SignedFitsIfEnabled(n);
+        fits.enabled <== enabled;
+        fits.in <== s[0].out * s[1].out;
+    }
+}
+
+// Division and remainder of n-bit integers. The quotient and remainder are
+// provided as hints and checked against `in[0] === quotient * in[1] + remainder`
+// with `remainder < in[1]`. Dividing by zero (or `INT_MIN` by -1 for the signed
+// variant) is undefined behaviour and makes the circuit unsatisfiable on the
+// executed path (`enabled`), while not executed paths accept any divisor.
+// With `exact` a non-zero remainder is forbidden as well.
+
+template UDivRem(n, exact) {
+    // The product of the quotient and the divisor has to fit in the field.
+    assert(n <= 126);
+    signal input in[2];
+    signal input enabled;
+    signal output quotient;
+    signal output remainder;
+
+    quotient <-- in[1] == 0 ? 0 : in[0] \ in[1];
+    remainder <-- in[1] == 0 ? in[0] : in[0] % in[1];
+    in[0] === quotient * in[1] + remainder;
+
+    component quotientRange = Num2Bits(n);
+    quotientRange.in <== quotient;
+    component remainderRange = Num2Bits(n);
+    remainderRange.in <== remainder;
+
+    component isZero = IsZero();
+    isZero.in <== in[1];
+    isZero.out * enabled === 0;
+
+    component lt = LessThan(n);
+    lt.in[0] <== remainder;
+    lt.in[1] <== in[1];
+    (1 - lt.out) * enabled === 0;
+
+    if (exact == 1) {
+        remainder * enabled === 0;
+    }
+}
+
+// Rounds towards zero, the remainder takes the sign of the dividend.
+template SDivRem(n, exact) {
+    assert(n <= 126);
+    signal input in[2];
+    signal input enabled;
+    signal output quotient;
+    signal output remainder;
+
+    // Divide the magnitudes, `INT_MIN` having the magnitude 2^(n-1).
+    component n2b[2];
+    signal sign[2];
+    signal magnitude[2];
+    for (var i = 0; i < 2; i++) {
+        n2b[i] = Num2Bits(n);
+        n2b[i].in <== in[i];
+        sign[i] <== n2b[i].out[n-1];
+        magnitude[i] <== in[i] + sign[i] * (2**n - 2 * in[i]);
+    }
+
+    component div = UDivRem(n, exact);
+    div.in[0] <== magnitude[0];
+    div.in[1] <== magnitude[1];
+    div.enabled <== enabled;
+
+    component neg[2];
+    for (var i = 0; i < 2; i++) {
+        neg[i] = IntSub(n, 0, 0);
+        neg[i].in[0] <== 0;
+        neg[i].enabled <== enabled;
+    }
+    neg[0].in[1] <== div.quotient;
+    neg[1].in[1] <== div.remainder;
+
+    signal negativeQuotient;
+    negativeQuotient <== sign[0] + sign[1] - 2 * sign[0] * sign[1];
+    quotient <== div.quotient + negativeQuotient * (neg[0].out - div.quotient);
+    remainder <== div.remainder + sign[0] * (neg[1].out - div.remainder);
+
+    component isMin = IsEqual();
+    isMin.in[0] <== in[0];
+    isMin.in[1] <== 2**(n-1);
+    component isMinusOne = IsEqual();
+    isMinusOne.in[0] <== in[1];
+    isMinusOne.in[1] <== 2**n - 1;
+    signal overflow;
+    overflow <== isMin.out * isMinusOne.out;
+    overflow * enabled === 0;
+}
+
+// The `llvm.*.with.overflow` intrinsics: the wrapped n-bit result together with
+// a flag telling whether the operation overflowed, as unsigned or as signed
+// (two's complement) integers. Overflowing is not undefined behaviour here,
+// so nothing depends on the executed path.
+
+template AddWithOverflow(n, signed) {
+    assert(n <= 252);
+    signal input in[2];
+    signal output out;
+    signal output overflow;
+
+    component sum = Num2Bits(n + 1);
+    sum.in <== in[0] + in[1];
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== sum.out[i];
+    }
+    out <== result.out;
+
+    component a;
+    component b;
+    signal sameSign;
+    if (signed == 0) {
+        overflow <== sum.out[n];
+    } else {
+        // Both operands have the same sign and the result has the other one.
+        a = Num2Bits(n);
+        b = Num2Bits(n);
+        a.in <== in[0];
+        b.in <== in[1];
+        sameSign <== 1 - (a.out[n-1] + b.out[n-1] - 2 * a.out[n-1] * b.out[n-1]);
+        overflow <== sameSign * (a.out[n-1] + sum.out[n-1] - 2 * a.out[n-1] * sum.out[n-1]);
+    }
+}
+
+template SubWithOverflow(n, signed) {
+    assert(n <= 252);
+    signal input in[2];
+    signal output out;
+    signal output overflow;
+
+    component difference = Num2Bits(n + 1);
+    difference.in <== in[0] - in[1] + 2**n;
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== difference.out[i];
+    }
+    out <== result.out;
+
+    component a;
+    component b;
+    signal differentSign;
+    if (signed == 0) {
+        overflow <== 1 - difference.out[n];
+    } else {
+        // The operands have different signs and the result has the sign of the subtrahend.
+        a = Num2Bits(n);
+        b = Num2Bits(n);
+        a.in <== in[0];
+        b.in <== in[1];
+        differentSign <== a.out[n-1] + b.out[n-1] - 2 * a.out[n-1] * b.out[n-1];
+        overflow <== differentSign * (a.out[n-1] + difference.out[n-1] - 2 * a.out[n-1] * difference.out[n-1]);
+    }
+}
+
+template MulWithOverflow(n, signed) {
+    // The full product has to fit in the field.
+    assert(n <= 126);
+    signal input in[2];
+    signal output out;
+    signal output overflow;
+
+    component product = Num2Bits(2 * n);
+    product.in <== in[0] * in[1];
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== product.out[i];
+    }
+    out <== result.out;
+
+    // The product fits when its high half is zero (unsigned), or when the signed
+    // product shifted by 2^(n-1) + 2^(2n-1) has 2^(n-1) as its high half (signed).
+    component high = Bits2Num(n);
+    component zero;
+    component s[2];
+    component shifted;
+    component fits;
+    if (signed == 0) {
+        for (var i = 0; i < n; i++) {
+            high.in[i] <== product.out[n + i];
+        }
+        zero = IsZero();
+        zero.in <== high.out;
+        overflow <== 1 - zero.out;
+    } else {
+        s[0] = SignedValue(n);
+        s[1] = SignedValue(n);
+        s[0].in <== in[0];
+        s[1].in <== in[1];
+        shifted = Num2Bits(2 * n);
+        shifted.in <== s[0].out * s[1].out + 2**(n-1) + 2**(2*n-1);
+        for (var i = 0; i < n; i++) {
+            high.in[i] <== shifted.out[n + i];
+        }
+        fits = IsEqual();
+        fits.in[0] <== high.out;
+        fits.in[1] <== 2**(n-1);
+        overflow <== 1 - fits.out;
+    }
+}
@@ -0,0 +1,257 @@
+pragma circom 2.0.0;
+
+include "../circomlib/bitify.circom";
+include "../circomlib/comparators.circom";
+include "./arithmetic.circom";
+
+// Bitwise operations on n-bit integers, computed bit by bit on their
+// decompositions.
+
+template IntAnd(n) {
+    signal input in[2];
+    signal output out;
+
+    component a = Num2Bits(n);
+    component b = Num2Bits(n);
+    a.in <== in[0];
+    b.in <== in[1];
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== a.out[i] * b.out[i];
+    }
+    out <== result.out;
+}
+
+template IntOr(n) {
+    signal input in[2];
+    signal output out;
+
+    component a = Num2Bits(n);
+    component b = Num2Bits(n);
+    a.in <== in[0];
+    b.in <== in[1];
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== a.out[i] + b.out[i] - a.out[i] * b.out[i];
+    }
+    out <== result.out;
+}
+
+template IntXor(n) {
+    signal input in[2];
+    signal output out;
+
+    component a = Num2Bits(n);
+    component b = Num2Bits(n);
+    a.in <== in[0];
+    b.in <== in[1];
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== a.out[i] + b.out[i] - 2 * a.out[i] * b.out[i];
+    }
+    out <== result.out;
+}
+
+// Shifts by a constant amount k < n only rewire the bits of the decomposition.
+// Like the arithmetic templates, the `nuw`/`nsw`/`exact` flags forbid shifting
+// out the bits they cover, but only on the executed path (`enabled`).
+
+template ShlConst(n, k, nuw, nsw) {
+    assert(k < n);
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        if (i < k) {
+            result.in[i] <== 0;
+        } else {
+            result.in[i] <== bits.out[i - k];
+        }
+    }
+    out <== result.out;
+
+    // The shifted out bits have to be zero (nuw) or all equal the result sign (nsw).
+    var shiftedOut = 0;
+    for (var i = n - k; i < n; i++) {
+        shiftedOut += bits.out[i];
+    }
+    if (nuw == 1) {
+        shiftedOut * enabled === 0;
+    }
+    if (nsw == 1) {
+        for (var i = n - k - 1; i < n; i++) {
+            (bits.out[i] - bits.out[n - k - 1]) * enabled === 0;
+        }
+    }
+}
+
+template LShrConst(n, k, exact) {
+    assert(k < n);
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        if (i + k < n) {
+            result.in[i] <== bits.out[i + k];
+        } else {
+            result.in[i] <== 0;
+        }
+    }
+    out <== result.out;
+
+    var shiftedOut = 0;
+    for (var i = 0; i < k; i++) {
+        shiftedOut += bits.out[i];
+    }
+    if (exact == 1) {
+        shiftedOut * enabled === 0;
+    }
+}
+
+template AShrConst(n, k, exact) {
+    assert(k < n);
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        if (i + k < n) {
+            result.in[i] <== bits.out[i + k];
+        } else {
+            result.in[i] <== bits.out[n - 1];
+        }
+    }
+    out <== result.out;
+
+    var shiftedOut = 0;
+    for (var i = 0; i < k; i++) {
+        shiftedOut += bits.out[i];
+    }
+    if (exact == 1) {
+        shiftedOut * enabled === 0;
+    }
+}
+
+// 2^amount for a shift amount of an n-bit integer. Shifting by n or more bits
+// yields poison, so it is forbidden on the executed path (`enabled`); otherwise
+// only the bits of the amount below n are used.
+template ShiftPower(n) {
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    var k = 1;
+    while ((1 << k) < n) {
+        k++;
+    }
+
+    component amount = Num2Bits(n);
+    amount.in <== in;
+    component lt = LessThan(n);
+    lt.in[0] <== in;
+    lt.in[1] <== n;
+    (1 - lt.out) * enabled === 0;
+
+    signal power[k + 1];
+    power[0] <== 1;
+    for (var j = 0; j < k; j++) {
+        power[j + 1] <== power[j] * (1 + amount.out[j] * (2**(2**j) - 1));
+    }
+    out <== power[k];
+}
+
+// Shifting left by a variable amount is a multiplication by 2^amount.
+template Shl(n, nuw, nsw) {
+    assert(n <= 126);
+    signal input in[2];
+    signal input enabled;
+    signal output out;
+
+    component power = ShiftPower(n);
+    power.in <== in[1];
+    power.enabled <== enabled;
+
+    signal product;
+    product <== in[0] * power.out;
+    signal quotient;
+    quotient <-- product \ 2**n;
+    out <-- product % 2**n;
+    product === quotient * 2**n + out;
+
+    component range = Num2Bits(n);
+    range.in <== out;
+    component quotientRange = Num2Bits(n);
+    quotientRange.in <== quotient;
+
+    component s;
+    component fits;
+    if (nuw == 1) {
+        quotient * enabled === 0;
+    }
+    if (nsw == 1) {
+        s = SignedValue(n);
+        s.in <== in[0];
+        fits = SignedFitsIfEnabled(n);
+        fits.enabled <== enabled;
+        fits.in <== s.out * power.out;
+    }
+}
+
+// Shifting right by a variable amount is a division by 2^amount.
+template LShr(n, exact) {
+    assert(n <= 126);
+    signal input in[2];
+    signal input enabled;
+    signal output out;
+
+    component power = ShiftPower(n);
+    power.in <== in[1];
+    power.enabled <== enabled;
+
+    component div = UDivRem(n, exact);
+    div.in[0] <== in[0];
+    div.in[1] <== power.out;
+    div.enabled <== enabled;
+    out <== div.quotient;
+}
+
+// The arithmetic shift of x is the complement of the logical shift of ~x.
+template AShr(n, exact) {
+    assert(n <= 126);
+    signal input in[2];
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in[0];
+    signal sign;
+    sign <== bits.out[n - 1];
+
+    component power = ShiftPower(n);
+    power.in <== in[1];
+    power.enabled <== enabled;
+
+    component div = UDivRem(n, 0);
+    div.in[0] <== in[0] + sign * (2**n - 1 - 2 * in[0]);
+    div.in[1] <== power.out;
+    div.enabled <== enabled;
+    out <== div.quotient + sign * (2**n - 1 - 2 * div.quotient);
+
+    // The shifted out bits of ~x are all ones exactly when the ones of x are zeroes.
+    signal shiftedOut;
+    shiftedOut <== div.remainder - sign * (power.out - 1);
+    if (exact == 1) {
+        shiftedOut * enabled === 0;
+    }
+}
@@ -0,0 +1,61 @@
+pragma circom 2.0.0;
+
+include "../circomlib/comparators.circom";
+
+// LLVM integers are represented as their unsigned n-bit value.
+// Flipping the sign bit maps the two's complement order onto the unsigned one,
+// so the signed comparators reuse the circomlib ones on the flipped values.
+template SignFlip(n) {
+    signal input in;
+    signal output out;
+
+    component n2b = Num2Bits(n);
+    n2b.in <== in;
+
+    out <== in + 2**(n-1) - n2b.out[n-1] * 2**n;
+}
+
+template SignedLessThan(n) {
+    signal input in[2];
+    signal output out;
+
+    component f0 = SignFlip(n);
+    component f1 = SignFlip(n);
+    f0.in <== in[0];
+    f1.in <== in[1];
+
+    component lt = LessThan(n);
+    lt.in[0] <== f0.out;
+    lt.in[1] <== f1.out;
+    lt.out ==> out;
+}
+
+template SignedLessEqThan(n) {
+    signal input in[2];
+    signal output out;
+
+    component gt = SignedLessThan(n);
+    gt.in[0] <== in[1];
+    gt.in[1] <== in[0];
+    out <== 1 - gt.out;
+}
+
+template SignedGreaterThan(n) {
+    signal input in[2];
+    signal output out;
+
+    component lt = SignedLessThan(n);
+    lt.in[0] <== in[1];
+    lt.in[1] <== in[0];
+    lt.out ==> out;
+}
+
+template SignedGreaterEqThan(n) {
+    signal input in[2];
+    signal output out;
+
+    component lt = SignedLessThan(n);
+    lt.in[0] <== in[0];
+    lt.in[1] <== in[1];
+    out <== 1 - lt.out;
+}
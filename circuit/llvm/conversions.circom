@@ -0,0 +1,32 @@
+pragma circom 2.0.0;
+
+include "../circomlib/bitify.circom";
+
+// Integer conversions between an n-bit and an m-bit type. `zext` needs no
+// template since the value of the integer is unchanged.
+
+// Keeps the low m bits of an n-bit integer.
+template Trunc(n, m) {
+    assert(m < n);
+    signal input in;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    component result = Bits2Num(m);
+    for (var i = 0; i < m; i++) {
+        result.in[i] <== bits.out[i];
+    }
+    out <== result.out;
+}
+
+// Replicates the sign bit of an n-bit integer into the high bits of an m-bit one.
+template SignExtend(n, m) {
+    assert(n < m);
+    signal input in;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    out <== in + bits.out[n-1] * (2**m - 2**n);
+}
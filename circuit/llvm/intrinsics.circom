@@ -0,0 +1,196 @@
+pragma circom 2.0.0;
+
+include "../circomlib/bitify.circom";
+include "../circomlib/comparators.circom";
+include "./comparators.circom";
+
+// LLVM integer intrinsics on n-bit integers. The ones flagged to return poison
+// for some inputs forbid those inputs on the executed path (`enabled`).
+
+template UMin(n) {
+    signal input in[2];
+    signal output out;
+
+    component lt = LessThan(n);
+    lt.in[0] <== in[0];
+    lt.in[1] <== in[1];
+    out <== in[1] + lt.out * (in[0] - in[1]);
+}
+
+template UMax(n) {
+    signal input in[2];
+    signal output out;
+
+    component lt = LessThan(n);
+    lt.in[0] <== in[0];
+    lt.in[1] <== in[1];
+    out <== in[0] + lt.out * (in[1] - in[0]);
+}
+
+template SMin(n) {
+    signal input in[2];
+    signal output out;
+
+    component lt = SignedLessThan(n);
+    lt.in[0] <== in[0];
+    lt.in[1] <== in[1];
+    out <== in[1] + lt.out * (in[0] - in[1]);
+}
+
+template SMax(n) {
+    signal input in[2];
+    signal output out;
+
+    component lt = SignedLessThan(n);
+    lt.in[0] <== in[0];
+    lt.in[1] <== in[1];
+    out <== in[0] + lt.out * (in[1] - in[0]);
+}
+
+// `abs` of INT_MIN wraps to INT_MIN, or is poison with `intMinPoison`.
+template Abs(n, intMinPoison) {
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    out <== in + bits.out[n-1] * (2**n - 2 * in);
+
+    component isMin;
+    if (intMinPoison == 1) {
+        isMin = IsEqual();
+        isMin.in[0] <== in;
+        isMin.in[1] <== 2**(n-1);
+        isMin.out * enabled === 0;
+    }
+}
+
+template Ctpop(n) {
+    signal input in;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    var count = 0;
+    for (var i = 0; i < n; i++) {
+        count += bits.out[i];
+    }
+    out <== count;
+}
+
+// Counts the leading zeroes as the number of all-zero prefixes of the bits,
+// from the most significant one. A zero input has n leading zeroes, or is
+// poison with `zeroPoison`.
+template Ctlz(n, zeroPoison) {
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    signal zeroes[n + 1];
+    zeroes[n] <== 1;
+    var count = 0;
+    for (var i = n - 1; i >= 0; i--) {
+        zeroes[i] <== zeroes[i + 1] * (1 - bits.out[i]);
+        count += zeroes[i];
+    }
+    out <== count;
+
+    if (zeroPoison == 1) {
+        zeroes[0] * enabled === 0;
+    }
+}
+
+// Same as `Ctlz`, from the least significant bit.
+template Cttz(n, zeroPoison) {
+    signal input in;
+    signal input enabled;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    signal zeroes[n + 1];
+    zeroes[0] <== 1;
+    var count = 0;
+    for (var i = 0; i < n; i++) {
+        zeroes[i + 1] <== zeroes[i] * (1 - bits.out[i]);
+        count += zeroes[i + 1];
+    }
+    out <== count;
+
+    if (zeroPoison == 1) {
+        zeroes[n] * enabled === 0;
+    }
+}
+
+// Reverses the bytes, only rewiring the bits.
+template Bswap(n) {
+    assert(n % 16 == 0);
+    signal input in;
+    signal output out;
+
+    component bits = Num2Bits(n);
+    bits.in <== in;
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== bits.out[n - 8 * (i \ 8 + 1) + i % 8];
+    }
+    out <== result.out;
+}
+
+// `fshl(a, b, c)` / `fshr(a, b, c)`: the 2n-bit concatenation of a (high) and
+// b (low) is shifted by c modulo n, the result being its high (left) or low
+// (right) half. The shift is a barrel shifter on the bits of the concatenation.
+template FunnelShift(n, left) {
+    assert(n <= 126);
+    signal input in[3];
+    signal output out;
+
+    var k = 1;
+    while ((1 << k) < n) {
+        k++;
+    }
+
+    signal quotient;
+    signal amount;
+    quotient <-- in[2] \ n;
+    amount <-- in[2] % n;
+    in[2] === quotient * n + amount;
+    component quotientRange = Num2Bits(n);
+    quotientRange.in <== quotient;
+    component amountBits = Num2Bits(n);
+    amountBits.in <== amount;
+    component lt = LessThan(n);
+    lt.in[0] <== amount;
+    lt.in[1] <== n;
+    lt.out === 1;
+
+    component low = Num2Bits(n);
+    component high = Num2Bits(n);
+    low.in <== in[1];
+    high.in <== in[0];
+    signal stage[k + 1][2 * n];
+    for (var i = 0; i < n; i++) {
+        stage[0][i] <== low.out[i];
+        stage[0][n + i] <== high.out[i];
+    }
+    for (var j = 0; j < k; j++) {
+        var step = 2**j;
+        for (var i = 0; i < 2 * n; i++) {
+            var source = left == 1 ? i - step : i + step;
+            if (source >= 0 && source < 2 * n) {
+                stage[j + 1][i] <== stage[j][i] + amountBits.out[j] * (stage[j][source] - stage[j][i]);
+            } else {
+                stage[j + 1][i] <== stage[j][i] - amountBits.out[j] * stage[j][i];
+            }
+        }
+    }
+
+    component result = Bits2Num(n);
+    for (var i = 0; i < n; i++) {
+        result.in[i] <== stage[k][left == 1 ? n + i : i];
+    }
+    out <== result.out;
+}
@@ -0,0 +1,43 @@
+pragma circom 2.0.0;
+
+include "../circomlib/comparators.circom";
+
+// Accesses to memory slots through a dynamic index. An index out of bounds is
+// undefined behaviour, so it is only allowed off the executed path (`enabled`).
+
+// One flag per slot, set for the slot the index points to.
+template IndexDecoder(n) {
+    signal input index;
+    signal input enabled;
+    signal output out[n];
+
+    component eq[n];
+    var total = 0;
+    for (var i = 0; i < n; i++) {
+        eq[i] = IsEqual();
+        eq[i].in[0] <== index;
+        eq[i].in[1] <== i;
+        out[i] <== eq[i].out;
+        total += out[i];
+    }
+    (1 - total) * enabled === 0;
+}
+
+// The value of the slot the index points to.
+template IndexSelector(n) {
+    signal input in[n];
+    signal input index;
+    signal input enabled;
+    signal output out;
+
+    component decoder = IndexDecoder(n);
+    decoder.index <== index;
+    decoder.enabled <== enabled;
+    signal terms[n];
+    var sum = 0;
+    for (var i = 0; i < n; i++) {
+        terms[i] <== decoder.out[i] * in[i];
+        sum += terms[i];
+    }
+    out <== sum;
+}
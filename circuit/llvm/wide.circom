@@ -0,0 +1,510 @@
+pragma circom 2.0.0;
+
+include "../circomlib/bitify.circom";
+include "../circomlib/comparators.circom";
+
+// Integers wider than 64 bits do not fit the arithmetic of a single field
+// element. They are k limbs of 64 bits, least significant first, the most
+// significant one having `top` bits. Input limbs are expected to be in range,
+// output limbs are constrained to be.
+
+// Signed overflow of `a + b = r` (or `a - b = r` with `sub`), from the top
+// limbs of a, b and r: the operands have the same sign (different signs for
+// sub) and the result has the other one.
+template SignedOverflow(top, sub) {
+    signal input in[3];
+    signal output out;
+
+    component signs[3];
+    for (var j = 0; j < 3; j++) {
+        signs[j] = Num2Bits(top);
+        signs[j].in <== in[j];
+    }
+    signal a;
+    signal b;
+    signal r;
+    a <== signs[0].out[top - 1];
+    b <== signs[1].out[top - 1];
+    r <== signs[2].out[top - 1];
+    signal differ;
+    differ <== a + b - 2 * a * b;
+    signal flipped;
+    flipped <== a + r - 2 * a * r;
+    if (sub == 1) {
+        out <== differ * flipped;
+    } else {
+        out <== (1 - differ) * flipped;
+    }
+}
+
+// Wrapping addition with a carry chain; `nuw`/`nsw` overflows are forbidden
+// on the executed path (`enabled`).
+template WideAdd(k, top, nuw, nsw) {
+    signal input in[2][k];
+    signal input enabled;
+    signal output out[k];
+    signal output carryOut;
+
+    signal carry[k];
+    component range[k];
+    for (var i = 0; i < k; i++) {
+        var bits = 64;
+        if (i == k - 1) {
+            bits = top;
+        }
+        var sum = in[0][i] + in[1][i];
+        if (i > 0) {
+            sum += carry[i - 1];
+        }
+        carry[i] <-- sum \ 2**bits;
+        out[i] <-- sum % 2**bits;
+        carry[i] * (carry[i] - 1) === 0;
+        sum === out[i] + carry[i] * 2**bits;
+        range[i] = Num2Bits(bits);
+        range[i].in <== out[i];
+    }
+    carryOut <== carry[k - 1];
+
+    component overflow;
+    if (nuw == 1) {
+        carry[k - 1] * enabled === 0;
+    }
+    if (nsw == 1) {
+        overflow = SignedOverflow(top, 0);
+        overflow.in[0] <== in[0][k - 1];
+        overflow.in[1] <== in[1][k - 1];
+        overflow.in[2] <== out[k - 1];
+        overflow.out * enabled === 0;
+    }
+}
+
+// Wrapping subtraction with a borrow chain.
+template WideSub(k, top, nuw, nsw) {
+    signal input in[2][k];
+    signal input enabled;
+    signal output out[k];
+    signal output borrowOut;
+
+    signal borrow[k];
+    component range[k];
+    for (var i = 0; i < k; i++) {
+        var bits = 64;
+        if (i == k - 1) {
+            bits = top;
+        }
+        var subtrahend = in[1][i];
+        if (i > 0) {
+            subtrahend += borrow[i - 1];
+        }
+        borrow[i] <-- in[0][i] < subtrahend ? 1 : 0;
+        out[i] <-- in[0][i] - subtrahend + borrow[i] * 2**bits;
+        borrow[i] * (borrow[i] - 1) === 0;
+        in[0][i] - subtrahend + borrow[i] * 2**bits === out[i];
+        range[i] = Num2Bits(bits);
+        range[i].in <== out[i];
+    }
+    borrowOut <== borrow[k - 1];
+
+    component overflow;
+    if (nuw == 1) {
+        borrow[k - 1] * enabled === 0;
+    }
+    if (nsw == 1) {
+        overflow = SignedOverflow(top, 1);
+        overflow.in[0] <== in[0][k - 1];
+        overflow.in[1] <== in[1][k - 1];
+        overflow.in[2] <== out[k - 1];
+        overflow.out * enabled === 0;
+    }
+}
+
+// Wrapping schoolbook multiplication: column j gathers the limb products of
+// weight 2^(64 j), the carries being propagated from the lowest column. `high`
+// sums the columns beyond the width and the last carry, it is zero exactly
+// when the unsigned product fits, as `nuw` requires. The signed overflow
+// (`nsw`) is not checked, the translator rejects `mul nsw` on wide integers.
+template WideMul(k, top, nuw) {
+    signal input in[2][k];
+    signal input enabled;
+    signal output out[k];
+    signal output high;
+
+    var kBits = 1;
+    while ((1 << kBits) <= k) {
+        kBits++;
+    }
+
+    signal products[k][k];
+    for (var i = 0; i < k; i++) {
+        for (var l = 0; l < k; l++) {
+            products[i][l] <== in[0][i] * in[1][l];
+        }
+    }
+
+    signal carry[k];
+    component range[k];
+    component carryRange[k];
+    for (var j = 0; j < k; j++) {
+        var bits = 64;
+        if (j == k - 1) {
+            bits = top;
+        }
+        var column = 0;
+        for (var i = 0; i <= j; i++) {
+            column += products[i][j - i];
+        }
+        if (j > 0) {
+            column += carry[j - 1];
+        }
+        carry[j] <-- column \ 2**bits;
+        out[j] <-- column % 2**bits;
+        column === out[j] + carry[j] * 2**bits;
+        range[j] = Num2Bits(bits);
+        range[j].in <== out[j];
+        carryRange[j] = Num2Bits(129 + kBits - bits);
+        carryRange[j].in <== carry[j];
+    }
+
+    var beyond = carry[k - 1];
+    for (var i = 0; i < k; i++) {
+        for (var l = k - i; l < k; l++) {
+            beyond += products[i][l];
+        }
+    }
+    high <== beyond;
+    if (nuw == 1) {
+        high * enabled === 0;
+    }
+}
+
+// `llvm.{u,s}add.with.overflow`: the wrapped sum and whether it overflowed.
+template WideAddWithOverflow(k, top, signed) {
+    signal input in[2][k];
+    signal output out[k];
+    signal output overflow;
+
+    component add = WideAdd(k, top, 0, 0);
+    for (var i = 0; i < k; i++) {
+        add.in[0][i] <== in[0][i];
+        add.in[1][i] <== in[1][i];
+    }
+    add.enabled <== 0;
+    for (var i = 0; i < k; i++) {
+        out[i] <== add.out[i];
+    }
+
+    component signs;
+    if (signed == 0) {
+        overflow <== add.carryOut;
+    } else {
+        signs = SignedOverflow(top, 0);
+        signs.in[0] <== in[0][k - 1];
+        signs.in[1] <== in[1][k - 1];
+        signs.in[2] <== add.out[k - 1];
+        overflow <== signs.out;
+    }
+}
+
+// `llvm.{u,s}sub.with.overflow`: the wrapped difference and whether it
+// overflowed.
+template WideSubWithOverflow(k, top, signed) {
+    signal input in[2][k];
+    signal output out[k];
+    signal output overflow;
+
+    component sub = WideSub(k, top, 0, 0);
+    for (var i = 0; i < k; i++) {
+        sub.in[0][i] <== in[0][i];
+        sub.in[1][i] <== in[1][i];
+    }
+    sub.enabled <== 0;
+    for (var i = 0; i < k; i++) {
+        out[i] <== sub.out[i];
+    }
+
+    component signs;
+    if (signed == 0) {
+        overflow <== sub.borrowOut;
+    } else {
+        signs = SignedOverflow(top, 1);
+        signs.in[0] <== in[0][k - 1];
+        signs.in[1] <== in[1][k - 1];
+        signs.in[2] <== sub.out[k - 1];
+        overflow <== signs.out;
+    }
+}
+
+// Absolute value of a two's complement integer, as an unsigned integer of the
+// same width (the minimum value is its own absolute value), with its sign.
+template WideAbs(k, top) {
+    signal input in[k];
+    signal output out[k];
+    signal output sign;
+
+    component bits = Num2Bits(top);
+    bits.in <== in[k - 1];
+    sign <== bits.out[top - 1];
+    component negation = WideSub(k, top, 0, 0);
+    for (var i = 0; i < k; i++) {
+        negation.in[0][i] <== 0;
+        negation.in[1][i] <== in[i];
+    }
+    negation.enabled <== 0;
+    for (var i = 0; i < k; i++) {
+        out[i] <== in[i] + sign * (negation.out[i] - in[i]);
+    }
+}
+
+// `llvm.{u,s}mul.with.overflow`: the wrapped product and whether it
+// overflowed. The signed product fits when the product of the absolute values
+// is below 2^(n-1), or equal to it for a negative product.
+template WideMulWithOverflow(k, top, signed) {
+    signal input in[2][k];
+    signal output out[k];
+    signal output overflow;
+
+    component mul = WideMul(k, top, 0);
+    for (var i = 0; i < k; i++) {
+        mul.in[0][i] <== in[0][i];
+        mul.in[1][i] <== in[1][i];
+    }
+    mul.enabled <== 0;
+    for (var i = 0; i < k; i++) {
+        out[i] <== mul.out[i];
+    }
+
+    component abs[2];
+    component magnitude;
+    component fits;
+    component topBits;
+    component limbZero[k];
+    component isMinimum;
+    signal negative;
+    signal belowMinimum;
+    signal minimum[k];
+    signal fitsSigned;
+    if (signed == 0) {
+        fits = IsZero();
+        fits.in <== mul.high;
+        overflow <== 1 - fits.out;
+    } else {
+        magnitude = WideMul(k, top, 0);
+        for (var j = 0; j < 2; j++) {
+            abs[j] = WideAbs(k, top);
+            for (var i = 0; i < k; i++) {
+                abs[j].in[i] <== in[j][i];
+                magnitude.in[j][i] <== abs[j].out[i];
+            }
+        }
+        magnitude.enabled <== 0;
+        negative <== abs[0].sign + abs[1].sign - 2 * abs[0].sign * abs[1].sign;
+
+        // The magnitude is 2^(n-1) when its top bit is the only one set.
+        topBits = Num2Bits(top);
+        topBits.in <== magnitude.out[k - 1];
+        belowMinimum <== 1 - topBits.out[top - 1];
+        for (var i = 0; i < k; i++) {
+            limbZero[i] = IsZero();
+            if (i == k - 1) {
+                limbZero[i].in <== magnitude.out[i] - 2**(top - 1);
+            } else {
+                limbZero[i].in <== magnitude.out[i];
+            }
+            if (i == 0) {
+                minimum[i] <== limbZero[i].out;
+            } else {
+                minimum[i] <== minimum[i - 1] * limbZero[i].out;
+            }
+        }
+        fitsSigned <== belowMinimum + negative * minimum[k - 1];
+        fits = IsZero();
+        fits.in <== magnitude.high;
+        overflow <== 1 - fits.out * fitsSigned;
+    }
+}
+
+// `llvm.{u,s}min` and `llvm.{u,s}max`, selecting the operands limb by limb.
+template WideMin(k, top, signed) {
+    signal input in[2][k];
+    signal output out[k];
+
+    component lt = WideLessThan(k, top, signed);
+    for (var i = 0; i < k; i++) {
+        lt.in[0][i] <== in[0][i];
+        lt.in[1][i] <== in[1][i];
+    }
+    for (var i = 0; i < k; i++) {
+        out[i] <== in[1][i] + lt.out * (in[0][i] - in[1][i]);
+    }
+}
+
+template WideMax(k, top, signed) {
+    signal input in[2][k];
+    signal output out[k];
+
+    component lt = WideLessThan(k, top, signed);
+    for (var i = 0; i < k; i++) {
+        lt.in[0][i] <== in[0][i];
+        lt.in[1][i] <== in[1][i];
+    }
+    for (var i = 0; i < k; i++) {
+        out[i] <== in[0][i] + lt.out * (in[1][i] - in[0][i]);
+    }
+}
+
+template WideIsEqual(k) {
+    signal input in[2][k];
+    signal output out;
+
+    component eq[k];
+    signal all[k + 1];
+    all[0] <== 1;
+    for (var i = 0; i < k; i++) {
+        eq[i] = IsEqual();
+        eq[i].in[0] <== in[0][i];
+        eq[i].in[1] <== in[1][i];
+        all[i + 1] <== all[i] * eq[i].out;
+    }
+    out <== all[k];
+}
+
+// `a < b`, decided by the most significant limb that differs. Signed
+// comparisons flip the sign bit of the top limbs first.
+template WideLessThan(k, top, signed) {
+    signal input in[2][k];
+    signal output out;
+
+    signal high[2];
+    component signs[2];
+    for (var j = 0; j < 2; j++) {
+        if (signed == 1) {
+            signs[j] = Num2Bits(top);
+            signs[j].in <== in[j][k - 1];
+            high[j] <== in[j][k - 1] + (1 - 2 * signs[j].out[top - 1]) * 2**(top - 1);
+        } else {
+            high[j] <== in[j][k - 1];
+        }
+    }
+
+    component lt[k];
+    component eq[k];
+    signal result[k];
+    for (var i = 0; i < k; i++) {
+        eq[i] = IsEqual();
+        if (i == k - 1) {
+            lt[i] = LessThan(top);
+            lt[i].in[0] <== high[0];
+            lt[i].in[1] <== high[1];
+            eq[i].in[0] <== high[0];
+            eq[i].in[1] <== high[1];
+        } else {
+            lt[i] = LessThan(64);
+            lt[i].in[0] <== in[0][i];
+            lt[i].in[1] <== in[1][i];
+            eq[i].in[0] <== in[0][i];
+            eq[i].in[1] <== in[1][i];
+        }
+        if (i == 0) {
+            result[i] <== lt[i].out;
+        } else {
+            result[i] <== lt[i].out + eq[i].out * result[i - 1];
+        }
+    }
+    out <== result[k - 1];
+}
+
+// Shifts by a variable amount (`kind` 0: shl, 1: lshr, 2: ashr) with a barrel
+// shifter on the bits of the limbs. Shifting by the width or more yields
+// poison, so it is forbidden on the executed path. The `nuw`/`nsw`/`exact`
+// flags are not checked, the translator rejects the shifts carrying them.
+template WideShift(k, top, kind) {
+    var n = 64 * (k - 1) + top;
+    signal input in[k];
+    signal input amount[k];
+    signal input enabled;
+    signal output out[k];
+
+    var s = 1;
+    while ((1 << s) < n) {
+        s++;
+    }
+
+    component amountBits = Num2Bits(64);
+    amountBits.in <== amount[0];
+    component lt = LessThan(64);
+    lt.in[0] <== amount[0];
+    lt.in[1] <== n;
+    var high = 1 - lt.out;
+    for (var i = 1; i < k; i++) {
+        high += amount[i];
+    }
+    high * enabled === 0;
+
+    component bits[k];
+    signal stage[s + 1][n];
+    for (var i = 0; i < k; i++) {
+        var width = 64;
+        if (i == k - 1) {
+            width = top;
+        }
+        bits[i] = Num2Bits(width);
+        bits[i].in <== in[i];
+        for (var b = 0; b < width; b++) {
+            stage[0][64 * i + b] <== bits[i].out[b];
+        }
+    }
+    for (var j = 0; j < s; j++) {
+        var step = 1 << j;
+        for (var b = 0; b < n; b++) {
+            var source = b + step;
+            if (kind == 0) {
+                source = b - step;
+            }
+            if (source >= 0 && source < n) {
+                stage[j + 1][b] <== stage[j][b] + amountBits.out[j] * (stage[j][source] - stage[j][b]);
+            } else if (kind == 2) {
+                stage[j + 1][b] <== stage[j][b] + amountBits.out[j] * (stage[0][n - 1] - stage[j][b]);
+            } else {
+                stage[j + 1][b] <== stage[j][b] - amountBits.out[j] * stage[j][b];
+            }
+        }
+    }
+
+    component result[k];
+    for (var i = 0; i < k; i++) {
+        var width = 64;
+        if (i == k - 1) {
+            width = top;
+        }
+        result[i] = Bits2Num(width);
+        for (var b = 0; b < width; b++) {
+            result[i].in[b] <== stage[s][64 * i + b];
+        }
+        out[i] <== result[i].out;
+    }
+}
+
+// Sign extension between limb representations, a narrow integer being a
+// single limb of `topIn` bits.
+template WideSignExtend(kIn, topIn, kOut, topOut) {
+    signal input in[kIn];
+    signal output out[kOut];
+
+    component bits = Num2Bits(topIn);
+    bits.in <== in[kIn - 1];
+    signal sign;
+    sign <== bits.out[topIn - 1];
+    for (var i = 0; i < kOut; i++) {
+        var width = 64;
+        if (i == kOut - 1) {
+            width = topOut;
+        }
+        if (i < kIn - 1) {
+            out[i] <== in[i];
+        } else if (i == kIn - 1) {
+            out[i] <== in[i] + sign * (2**width - 2**topIn);
+        } else {
+            out[i] <== sign * (2**width - 1);
+        }
+    }
+}
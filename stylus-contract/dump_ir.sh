@@ -1,3 +1,2 @@
 cargo rustc --lib -- --emit=llvm-ir
-cp target/debug/deps/stylus_contract.ll .
-opt -strip-debug stylus_contract.ll -S -o stylus_contract.ll 
\ No newline at end of file
+cp target/debug/deps/stylus_contract.ll .
\ No newline at end of file